@@ -0,0 +1,80 @@
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use plagiarism_service::algorithm::{generate_byte_kgrams, rolling_hashes};
+use plagiarism_service::comparison_orchestrator::compare_documents_rabin_karp;
+use rustc_hash::FxHashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const ONE_MB: usize = 1024 * 1024;
+const K_CHAR: usize = 25;
+
+/// Roughly 1 MB of C-like source where every function body differs slightly.
+fn source_corpus(size: usize, seed: usize) -> String {
+    let mut corpus = String::with_capacity(size + 256);
+    let mut i = seed;
+    while corpus.len() < size {
+        corpus.push_str(&format!(
+            "int compute_{i}(int *values, int n) {{\n    int total = {};\n    for (int j = 0; j < n; j++) {{\n        total += values[j] * {};\n    }}\n    return total;\n}}\n\n",
+            i % 7,
+            i % 13
+        ));
+        i += 1;
+    }
+    corpus
+}
+
+/// The previous implementation: every window hashed from scratch, O(n * k).
+fn rehash_every_window(bytes: &[u8], k: usize) -> Vec<u64> {
+    bytes
+        .windows(k)
+        .map(|window| {
+            let mut hasher = DefaultHasher::new();
+            window.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+fn bench_kgram_hashing(c: &mut Criterion) {
+    let corpus = source_corpus(ONE_MB, 0);
+    let bytes = corpus.as_bytes();
+
+    let mut group = c.benchmark_group("byte_kgram_hashing_1mb");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.sample_size(20);
+    for k in [K_CHAR, 100] {
+        group.bench_with_input(BenchmarkId::new("rehash_every_window", k), &k, |b, &k| {
+            b.iter(|| rehash_every_window(black_box(bytes), k))
+        });
+        group.bench_with_input(BenchmarkId::new("rolling_hash", k), &k, |b, &k| {
+            b.iter(|| rolling_hashes(black_box(bytes), k))
+        });
+    }
+    group.finish();
+}
+
+fn bench_rabin_karp_comparison(c: &mut Criterion) {
+    let doc1 = source_corpus(ONE_MB, 0);
+    let doc2 = source_corpus(ONE_MB, 500);
+
+    let mut group = c.benchmark_group("rabin_karp_1mb");
+    group.throughput(Throughput::Bytes((doc1.len() + doc2.len()) as u64));
+    group.sample_size(10);
+    group.bench_function("generate_byte_kgrams", |b| {
+        b.iter(|| generate_byte_kgrams(black_box(doc1.as_bytes()), K_CHAR))
+    });
+    group.bench_function("compare_documents_rabin_karp", |b| {
+        b.iter(|| {
+            compare_documents_rabin_karp(
+                black_box(&doc1),
+                black_box(&doc2),
+                K_CHAR,
+                &FxHashSet::default(),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_kgram_hashing, bench_rabin_karp_comparison);
+criterion_main!(benches);
@@ -1,9 +1,9 @@
-use rustc_hash::FxHashSet;
+use crate::tokenizer::{TokenNormalization, Tokenizer};
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
 use std::fmt;
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 use std::io;
 
 #[derive(Debug)]
@@ -25,14 +25,37 @@ impl From<io::Error> for AlgorithmError {
     }
 }
 
+/// Fingerprints occurring more often than this in a document are too common to tell
+/// which occurrence was copied from where, so they are left out of match regions.
+const MAX_FINGERPRINT_OCCURRENCES: usize = 16;
+
 pub fn tokenize(text: &str) -> Vec<String> {
-    text.to_lowercase()
-        .split(|c: char| !c.is_alphanumeric())
-        .filter(|s| !s.is_empty())
-        .map(String::from)
+    tokenize_with_spans(text)
+        .into_iter()
+        .map(|(token, _)| token)
         .collect()
 }
 
+/// Same as [`tokenize`], but also returns the byte range of each token in `text`.
+pub fn tokenize_with_spans(text: &str) -> Vec<(String, (usize, usize))> {
+    let mut tokens = Vec::new();
+    let mut token_start = None;
+    for (offset, c) in text.char_indices() {
+        match (c.is_alphanumeric(), token_start) {
+            (true, None) => token_start = Some(offset),
+            (false, Some(start)) => {
+                tokens.push((text[start..offset].to_lowercase(), (start, offset)));
+                token_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = token_start {
+        tokens.push((text[start..].to_lowercase(), (start, text.len())));
+    }
+    tokens
+}
+
 pub fn generate_token_kgrams(tokens: &[String], k_val: usize) -> Vec<Vec<String>> {
     if k_val == 0 || tokens.len() < k_val {
         return Vec::new();
@@ -43,61 +66,205 @@ pub fn generate_token_kgrams(tokens: &[String], k_val: usize) -> Vec<Vec<String>
         .collect()
 }
 
+/// Version of the fingerprint scheme. Fingerprints computed under different versions
+/// must not be compared; bump it whenever any hashing constant or rule below changes.
+pub const FINGERPRINT_VERSION: u32 = 1;
+
+/// Seed XORed into the FNV offset basis so our fingerprints differ from plain FNV-1a.
+const FINGERPRINT_SEED: u64 = 0x5851_f42d_4c95_7f2d;
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Fixed 64-bit hash used for every fingerprint, unlike `DefaultHasher` whose output may
+/// change between Rust releases. It is seeded FNV-1a over the input bytes followed by
+/// the SplitMix64 finalizer to spread FNV's weak low bits over the whole word.
+#[derive(Clone, Copy)]
+struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    fn new() -> Self {
+        Self {
+            state: FNV_OFFSET_BASIS ^ FINGERPRINT_SEED,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state = (self.state ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(self) -> u64 {
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Stable fingerprint hash of a byte string. See [`StableHasher`].
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Stable hash of a whole k-gram. Each token is prefixed with its length (little-endian
+/// `u64`) so that e.g. `["ab", "c"]` and `["a", "bc"]` hash differently.
 pub fn hash_token_kgram(token_kgram: &[String]) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    token_kgram.hash(&mut hasher);
+    let mut hasher = StableHasher::new();
+    for token in token_kgram {
+        hasher.write(&(token.len() as u64).to_le_bytes());
+        hasher.write(token.as_bytes());
+    }
     hasher.finish()
 }
 
-pub fn generate_byte_kgrams(bytes: &[u8], k: usize) -> FxHashSet<(u64, usize)> {
-    bytes
-        .windows(k)
+/// Modulus of the polynomial rolling hash, the Mersenne prime 2^61 - 1.
+const ROLLING_HASH_MODULUS: u64 = (1 << 61) - 1;
+/// Base of the polynomial rolling hash, an arbitrary value below the modulus.
+const ROLLING_HASH_BASE: u64 = 0x0153_8b1c_4d2e_9f77;
+
+fn mul_mod(a: u64, b: u64) -> u64 {
+    let product = a as u128 * b as u128;
+    // x mod (2^61 - 1) == (x >> 61) + (x & (2^61 - 1)), folded until it fits.
+    let folded = (product as u64 & ROLLING_HASH_MODULUS) + (product >> 61) as u64;
+    let folded = (folded & ROLLING_HASH_MODULUS) + (folded >> 61);
+    if folded >= ROLLING_HASH_MODULUS {
+        folded - ROLLING_HASH_MODULUS
+    } else {
+        folded
+    }
+}
+
+fn add_mod(a: u64, b: u64) -> u64 {
+    let sum = a + b;
+    if sum >= ROLLING_HASH_MODULUS {
+        sum - ROLLING_HASH_MODULUS
+    } else {
+        sum
+    }
+}
+
+/// Hashes every window of `k` consecutive values with a Rabin-Karp polynomial rolling
+/// hash, `sum(v[i] * BASE^(k - 1 - i)) mod (2^61 - 1)`, in O(n) overall: each window is
+/// derived from the previous one by removing the outgoing value and adding the new one.
+pub fn rolling_hashes<T: Copy + Into<u64>>(values: &[T], k: usize) -> Vec<u64> {
+    if k == 0 || values.len() < k {
+        return Vec::new();
+    }
+
+    // Weight of the value leaving the window, BASE^(k - 1).
+    let outgoing_weight = (1..k).fold(1, |weight, _| mul_mod(weight, ROLLING_HASH_BASE));
+    let reduce = |value: T| value.into() % ROLLING_HASH_MODULUS;
+
+    let mut hash = values[..k].iter().fold(0, |hash, &value| {
+        add_mod(mul_mod(hash, ROLLING_HASH_BASE), reduce(value))
+    });
+    let mut hashes = Vec::with_capacity(values.len() - k + 1);
+    hashes.push(hash);
+    for (&outgoing, &incoming) in values.iter().zip(&values[k..]) {
+        let without_outgoing = add_mod(
+            hash,
+            ROLLING_HASH_MODULUS - mul_mod(reduce(outgoing), outgoing_weight),
+        );
+        hash = add_mod(
+            mul_mod(without_outgoing, ROLLING_HASH_BASE),
+            reduce(incoming),
+        );
+        hashes.push(hash);
+    }
+    hashes
+}
+
+/// Rolling hashes of every `k`-byte window, paired with the window's byte offset, in
+/// offset order.
+pub fn generate_byte_kgrams(bytes: &[u8], k: usize) -> Vec<(u64, usize)> {
+    rolling_hashes(bytes, k)
+        .into_iter()
         .enumerate()
-        .map(|(i, window)| {
-            let mut hasher = DefaultHasher::new();
-            window.hash(&mut hasher);
-            (hasher.finish(), i)
-        })
+        .map(|(i, hash)| (hash, i))
         .collect()
 }
 
+fn token_id(token: &str) -> u64 {
+    stable_hash(token.as_bytes())
+}
+
+/// Rolling hashes of every k-gram of tokens, paired with the index of its first token.
+/// Tokens are first mapped to numeric ids so each k-gram is hashed in O(1).
+pub fn generate_token_kgram_hashes(tokens: &[String], k_val: usize) -> Vec<(u64, usize)> {
+    let ids: Vec<u64> = tokens.iter().map(|token| token_id(token)).collect();
+    rolling_hashes(&ids, k_val)
+        .into_iter()
+        .enumerate()
+        .map(|(i, hash)| (hash, i))
+        .collect()
+}
+
+/// Selects fingerprints with robust winnowing (Schleimer, Wilkerson & Aiken, 2003).
+///
+/// In every window of `window_size` consecutive hashes the minimum is selected. When the
+/// minimum is tied, the hash selected for the previous window is kept if it is still in
+/// the window, otherwise the rightmost minimum is taken. A fingerprint is only recorded
+/// when the selection changes, so the result is ordered by position and contains each
+/// selected (hash, position) once. Inputs shorter than a window are treated as a single
+/// window.
+///
+/// Runs in O(n) using a deque of candidate positions whose hashes strictly increase
+/// from front to back: the front is always the rightmost minimum of the window.
 pub fn winnow_hashes(
     hashes_with_indices: &[(u64, usize)],
     window_size: usize,
-) -> HashSet<(u64, usize)> {
-    let mut selected_fingerprints = HashSet::new();
+) -> Vec<(u64, usize)> {
+    let mut selected_fingerprints = Vec::new();
 
     if window_size == 0 || hashes_with_indices.is_empty() {
         return selected_fingerprints;
     }
 
-    if hashes_with_indices.len() < window_size {
-        if let Some(min_entry) = hashes_with_indices
-            .iter()
-            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+    let window_size = window_size.min(hashes_with_indices.len());
+    let mut candidates: VecDeque<usize> = VecDeque::with_capacity(window_size);
+    let mut selected: Option<usize> = None;
+
+    for (i, &(hash, _)) in hashes_with_indices.iter().enumerate() {
+        while candidates
+            .back()
+            .is_some_and(|&back| hashes_with_indices[back].0 >= hash)
         {
-            selected_fingerprints.insert(*min_entry);
+            candidates.pop_back();
         }
-        return selected_fingerprints;
-    }
-
-    for i in 0..=(hashes_with_indices.len() - window_size) {
-        let current_window = &hashes_with_indices[i..i + window_size];
+        candidates.push_back(i);
 
-        if let Some(min_in_window) = current_window
-            .iter()
-            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+        if i + 1 < window_size {
+            continue;
+        }
+        let window_start = i + 1 - window_size;
+        while candidates
+            .front()
+            .is_some_and(|&front| front < window_start)
         {
-            selected_fingerprints.insert(*min_in_window);
+            candidates.pop_front();
+        }
+
+        let Some(&minimum) = candidates.front() else {
+            continue;
+        };
+        let keep_previous = selected.is_some_and(|previous| {
+            previous >= window_start
+                && hashes_with_indices[previous].0 == hashes_with_indices[minimum].0
+        });
+        if !keep_previous {
+            selected = Some(minimum);
+            selected_fingerprints.push(hashes_with_indices[minimum]);
         }
     }
     selected_fingerprints
 }
 
-pub fn calculate_jaccard_index(
-    set_a: &FxHashSet<(u64, usize)>,
-    set_b: &FxHashSet<(u64, usize)>,
-) -> f64 {
+pub fn calculate_jaccard_index<T: Eq + Hash>(set_a: &FxHashSet<T>, set_b: &FxHashSet<T>) -> f64 {
     if set_a.is_empty() && set_b.is_empty() {
         return 1.0;
     }
@@ -112,95 +279,760 @@ pub fn calculate_jaccard_index(
     intersection_size as f64 / union_size as f64
 }
 
+/// Share of `set` that is also found in `other`, i.e. |set ∩ other| / |set|. Unlike the
+/// Jaccard index, this is not diluted when `other` is much larger than `set`.
+pub fn calculate_containment<T: Eq + Hash>(set: &FxHashSet<T>, other: &FxHashSet<T>) -> f64 {
+    if set.is_empty() {
+        return 0.0;
+    }
+    set.intersection(other).count() as f64 / set.len() as f64
+}
+
+/// A stretch of text shared by two documents. Lines and columns are 1-based and the
+/// end position points at the last character of the region.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MatchRegion {
+    pub start_line_a: usize,
+    pub start_column_a: usize,
+    pub end_line_a: usize,
+    pub end_column_a: usize,
+    pub start_line_b: usize,
+    pub start_column_b: usize,
+    pub end_line_b: usize,
+    pub end_column_b: usize,
+    pub shared_fingerprints: usize,
+}
+
+impl MatchRegion {
+    fn range_a(&self) -> ((usize, usize), (usize, usize)) {
+        (
+            (self.start_line_a, self.start_column_a),
+            (self.end_line_a, self.end_column_a),
+        )
+    }
+
+    fn range_b(&self) -> ((usize, usize), (usize, usize)) {
+        (
+            (self.start_line_b, self.start_column_b),
+            (self.end_line_b, self.end_column_b),
+        )
+    }
+}
+
+/// Maps byte offsets of a document to 1-based line/column positions.
+struct LineIndex<'a> {
+    bytes: &'a [u8],
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let bytes = text.as_bytes();
+        let line_starts = std::iter::once(0)
+            .chain(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &b)| b == b'\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Self { bytes, line_starts }
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        // Count characters rather than bytes; byte k-grams may start inside a character.
+        let column = self.bytes[line_start..offset]
+            .iter()
+            .filter(|&&b| (b as i8) >= -0x40)
+            .count();
+        (line, column + 1)
+    }
+}
+
+/// Pairs up the positions of every fingerprint hash found in both documents.
+pub fn pair_shared_fingerprints(
+    fingerprints_a: &[(u64, usize)],
+    fingerprints_b: &[(u64, usize)],
+) -> Vec<(usize, usize)> {
+    let mut positions_b: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+    for &(hash, position) in fingerprints_b {
+        positions_b.entry(hash).or_default().push(position);
+    }
+    let mut positions_a: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+    for &(hash, position) in fingerprints_a {
+        if positions_b.contains_key(&hash) {
+            positions_a.entry(hash).or_default().push(position);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (hash, in_a) in &positions_a {
+        let in_b = &positions_b[hash];
+        if in_a.len() > MAX_FINGERPRINT_OCCURRENCES || in_b.len() > MAX_FINGERPRINT_OCCURRENCES {
+            continue;
+        }
+        for &position_a in in_a {
+            pairs.extend(in_b.iter().map(|&position_b| (position_a, position_b)));
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+struct RegionBuilder {
+    last_a: usize,
+    last_b: usize,
+    span_a: (usize, usize),
+    span_b: (usize, usize),
+    shared_fingerprints: usize,
+}
+
+/// Chains fingerprint pairs that advance together in both documents into regions.
+///
+/// `pairs` holds (position in A, position in B) fingerprint positions sorted by A, and
+/// `span_a`/`span_b` give the byte range each position covers. Two consecutive pairs
+/// belong to the same region when both positions move forward by at most `max_gap`.
+pub fn build_match_regions(
+    doc_a: &str,
+    doc_b: &str,
+    pairs: &[(usize, usize)],
+    span_a: impl Fn(usize) -> (usize, usize),
+    span_b: impl Fn(usize) -> (usize, usize),
+    max_gap: usize,
+) -> Vec<MatchRegion> {
+    let mut active: Vec<RegionBuilder> = Vec::new();
+    let mut closed: Vec<RegionBuilder> = Vec::new();
+
+    for &(position_a, position_b) in pairs {
+        let (still_active, done): (Vec<_>, Vec<_>) = active
+            .into_iter()
+            .partition(|region| region.last_a + max_gap >= position_a);
+        active = still_active;
+        closed.extend(done);
+
+        let (start_a, end_a) = span_a(position_a);
+        let (start_b, end_b) = span_b(position_b);
+        let extendable = active.iter_mut().find(|region| {
+            region.last_a < position_a
+                && region.last_b < position_b
+                && position_b <= region.last_b + max_gap
+        });
+        match extendable {
+            Some(region) => {
+                region.last_a = position_a;
+                region.last_b = position_b;
+                region.span_a = (region.span_a.0.min(start_a), region.span_a.1.max(end_a));
+                region.span_b = (region.span_b.0.min(start_b), region.span_b.1.max(end_b));
+                region.shared_fingerprints += 1;
+            }
+            None => active.push(RegionBuilder {
+                last_a: position_a,
+                last_b: position_b,
+                span_a: (start_a, end_a),
+                span_b: (start_b, end_b),
+                shared_fingerprints: 1,
+            }),
+        }
+    }
+    closed.extend(active);
+
+    let index_a = LineIndex::new(doc_a);
+    let index_b = LineIndex::new(doc_b);
+    let mut regions: Vec<MatchRegion> = closed
+        .into_iter()
+        .filter(|region| region.span_a.1 > region.span_a.0 && region.span_b.1 > region.span_b.0)
+        .map(|region| {
+            let (start_line_a, start_column_a) = index_a.position(region.span_a.0);
+            let (end_line_a, end_column_a) = index_a.position(region.span_a.1 - 1);
+            let (start_line_b, start_column_b) = index_b.position(region.span_b.0);
+            let (end_line_b, end_column_b) = index_b.position(region.span_b.1 - 1);
+            MatchRegion {
+                start_line_a,
+                start_column_a,
+                end_line_a,
+                end_column_a,
+                start_line_b,
+                start_column_b,
+                end_line_b,
+                end_column_b,
+                shared_fingerprints: region.shared_fingerprints,
+            }
+        })
+        .collect();
+    regions.sort_by_key(|region| (region.range_a(), region.range_b()));
+    regions
+}
+
+/// Merges regions that overlap in both documents, e.g. the same copied block reported
+/// by two different algorithms.
+pub fn merge_match_regions(mut regions: Vec<MatchRegion>) -> Vec<MatchRegion> {
+    regions.sort_by_key(|region| (region.range_a(), region.range_b()));
+    let mut merged: Vec<MatchRegion> = Vec::with_capacity(regions.len());
+    for region in regions {
+        let overlapping = merged.iter_mut().rev().find(|existing| {
+            let (start_a, end_a) = region.range_a();
+            let (start_b, end_b) = region.range_b();
+            let (existing_start_a, existing_end_a) = existing.range_a();
+            let (existing_start_b, existing_end_b) = existing.range_b();
+            start_a <= existing_end_a
+                && existing_start_a <= end_a
+                && start_b <= existing_end_b
+                && existing_start_b <= end_b
+        });
+        match overlapping {
+            Some(existing) => {
+                let (start_a, end_a) = existing.range_a();
+                let (start_b, end_b) = existing.range_b();
+                let start_a = start_a.min(region.range_a().0);
+                let end_a = end_a.max(region.range_a().1);
+                let start_b = start_b.min(region.range_b().0);
+                let end_b = end_b.max(region.range_b().1);
+                (existing.start_line_a, existing.start_column_a) = start_a;
+                (existing.end_line_a, existing.end_column_a) = end_a;
+                (existing.start_line_b, existing.start_column_b) = start_b;
+                (existing.end_line_b, existing.end_column_b) = end_b;
+                existing.shared_fingerprints += region.shared_fingerprints;
+            }
+            None => merged.push(region),
+        }
+    }
+    merged
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MossResult {
     pub score: f64,
     pub fingerprints_matched: usize,
     pub fingerprints_doc1: usize,
     pub fingerprints_doc2: usize,
+    /// Share of the fingerprints of document 1 also found in document 2.
+    pub containment_doc1: f64,
+    /// Share of the fingerprints of document 2 also found in document 1.
+    pub containment_doc2: f64,
+    pub match_regions: Vec<MatchRegion>,
+}
+
+/// Default number of tokens per k-gram used by [`compare_documents_moss_like`].
+pub const DEFAULT_MOSS_K: usize = 4;
+/// Default winnowing window, in k-grams, used by [`compare_documents_moss_like`].
+pub const DEFAULT_MOSS_WINDOW: usize = 5;
+
+/// Winnowed fingerprints of `doc`, selected exactly as [`compare_documents_moss_like`]
+/// selects them before exclusion. Empty when `doc` has fewer than `k` tokens.
+pub fn moss_fingerprints(
+    doc: &str,
+    tokenizer: &dyn Tokenizer,
+    normalization: TokenNormalization,
+    k: usize,
+    w: usize,
+) -> Vec<(u64, usize)> {
+    let tokens: Vec<String> = tokenizer
+        .tokenize(doc)
+        .iter()
+        .map(|token| token.normalized_text(normalization).to_string())
+        .collect();
+    winnow_hashes(&generate_token_kgram_hashes(&tokens, k), w)
 }
 
-pub fn compare_documents_moss_like(doc1: &str, doc2: &str) -> MossResult {
-    let tokens1 = tokenize(doc1);
-    let tokens2 = tokenize(doc2);
+/// Compares two documents with MOSS-style winnowed fingerprints of the token streams
+/// produced by `tokenizer` and normalized according to `normalization`, `k` tokens per
+/// k-gram and one fingerprint per window of `w` k-grams. Fingerprints found in `excluded`
+/// are dropped after winnowing, like those of base code in MOSS.
+pub fn compare_documents_moss_like(
+    doc1: &str,
+    doc2: &str,
+    tokenizer: &dyn Tokenizer,
+    normalization: TokenNormalization,
+    k: usize,
+    w: usize,
+    excluded: &FxHashSet<u64>,
+) -> MossResult {
+    let normalized_tokens = |doc: &str| -> (Vec<String>, Vec<(usize, usize)>) {
+        tokenizer
+            .tokenize(doc)
+            .iter()
+            .map(|token| (token.normalized_text(normalization).to_string(), token.span))
+            .unzip()
+    };
+    let (tokens1, spans1) = normalized_tokens(doc1);
+    let (tokens2, spans2) = normalized_tokens(doc2);
 
-    let k = 4;
-    let w = 5;
-    let kgrams1 = generate_token_kgrams(&tokens1, k);
-    let kgrams2 = generate_token_kgrams(&tokens2, k);
+    let hashes1 = generate_token_kgram_hashes(&tokens1, k);
+    let hashes2 = generate_token_kgram_hashes(&tokens2, k);
 
-    if kgrams1.is_empty() && kgrams2.is_empty() {
+    if hashes1.is_empty() && hashes2.is_empty() {
         // If both documents are empty (or result in no k-grams), they are considered identical.
         return MossResult {
             score: 1.0, // Jaccard index is 1.0 for two empty sets by some definitions
             fingerprints_matched: 0,
             fingerprints_doc1: 0,
             fingerprints_doc2: 0,
+            containment_doc1: 1.0,
+            containment_doc2: 1.0,
+            match_regions: Vec::new(),
         };
     }
 
-    if kgrams1.is_empty() || kgrams2.is_empty() {
+    if hashes1.is_empty() || hashes2.is_empty() {
         // If one document is empty (or results in no k-grams) and the other is not,
         // they have no similarity.
         return MossResult {
             score: 0.0,
             fingerprints_matched: 0,
-            fingerprints_doc1: kgrams1.len(), // Will be 0 if kgrams1 is empty
-            fingerprints_doc2: kgrams2.len(), // Will be 0 if kgrams2 is empty
+            fingerprints_doc1: hashes1.len(), // Will be 0 if hashes1 is empty
+            fingerprints_doc2: hashes2.len(), // Will be 0 if hashes2 is empty
+            containment_doc1: 0.0,
+            containment_doc2: 0.0,
+            match_regions: Vec::new(),
         };
     }
 
-    let hashes1: Vec<(u64, usize)> = kgrams1
-        .iter()
-        .enumerate()
-        .map(|(i, kgram)| (hash_token_kgram(kgram), i))
-        .collect();
-    let hashes2: Vec<(u64, usize)> = kgrams2
-        .iter()
-        .enumerate()
-        .map(|(i, kgram)| (hash_token_kgram(kgram), i))
-        .collect();
-
-    let fingerprints1 = winnow_hashes(&hashes1, w);
-    let fingerprints2 = winnow_hashes(&hashes2, w);
+    let mut fingerprints1 = winnow_hashes(&hashes1, w);
+    let mut fingerprints2 = winnow_hashes(&hashes2, w);
+    fingerprints1.retain(|(hash, _)| !excluded.contains(hash));
+    fingerprints2.retain(|(hash, _)| !excluded.contains(hash));
 
-    let hash_set1: HashSet<u64> = fingerprints1.iter().map(|&(hash, _)| hash).collect();
-    let hash_set2: HashSet<u64> = fingerprints2.iter().map(|&(hash, _)| hash).collect();
+    let hash_set1: FxHashSet<u64> = fingerprints1.iter().map(|&(hash, _)| hash).collect();
+    let hash_set2: FxHashSet<u64> = fingerprints2.iter().map(|&(hash, _)| hash).collect();
 
     let intersection_size = hash_set1.intersection(&hash_set2).count();
     let union_size = hash_set1.union(&hash_set2).count();
 
     let score = if union_size == 0 {
-        // This case should ideally be covered by the empty kgrams checks above.
-        // If both sets of fingerprints are empty, but kgrams were not, implies k > 0 but w made them empty.
-        // If kgrams were initially non-empty, but winnowing resulted in empty fingerprint sets for both,
-        // and union_size is 0, it means both docs produced 0 fingerprints.
-        // This implies they are effectively empty for comparison purposes after winnowing.
-        // If they both result in zero fingerprints, they could be considered identical (score 1.0)
-        // or completely different (score 0.0) depending on interpretation.
-        // Given tests expect 1.0 for empty inputs and 0.0 for one empty, zero fingerprints post-winnowing for both
-        // should imply that nothing meaningful could be extracted, so score should be 0.0 unless kgrams were *also* empty.
-        // Let's stick to 0.0 if union_size is 0 but kgrams were not empty, as it means no common fingerprints.
-        if hash_set1.is_empty() && hash_set2.is_empty() {
-            1.0 // If both fingerprint sets are empty, treat as identical.
-        } else {
-            0.0 // Should not happen if union_size is 0 and sets are not both empty.
-        }
+        // Winnowing always selects fingerprints from non-empty k-grams, so both sets can
+        // only be empty when every fingerprint was excluded: nothing is left to match.
+        0.0
     } else {
         intersection_size as f64 / union_size as f64
     };
 
+    // A k-gram starting at token `i` covers tokens `i..i + k`.
+    let kgram_span = |spans: &[(usize, usize)], i: usize| (spans[i].0, spans[i + k - 1].1);
+    let match_regions = build_match_regions(
+        doc1,
+        doc2,
+        &pair_shared_fingerprints(&fingerprints1, &fingerprints2),
+        |i| kgram_span(&spans1, i),
+        |i| kgram_span(&spans2, i),
+        w + k,
+    );
+
     MossResult {
         score,
         fingerprints_matched: intersection_size,
         fingerprints_doc1: hash_set1.len(),
         fingerprints_doc2: hash_set2.len(),
+        containment_doc1: calculate_containment(&hash_set1, &hash_set2),
+        containment_doc2: calculate_containment(&hash_set2, &hash_set1),
+        match_regions,
+    }
+}
+
+/// A maximal run of tokens shared by both documents, found by Greedy String Tiling.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    /// Index of the first token of the tile in document A.
+    pub start_a: usize,
+    /// Index of the first token of the tile in document B.
+    pub start_b: usize,
+    /// Number of tokens covered by the tile.
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GstResult {
+    /// Share of both token streams covered by tiles: `2 * covered / (|A| + |B|)`.
+    pub similarity: f64,
+    pub tiles: Vec<Tile>,
+    pub tokens_doc1: usize,
+    pub tokens_doc2: usize,
+    pub match_regions: Vec<MatchRegion>,
+}
+
+/// Default minimum number of tokens a Greedy String Tiling match must span.
+pub const DEFAULT_GST_MIN_MATCH_LENGTH: usize = 9;
+
+/// Length at which Greedy String Tiling starts searching for matches. Longer matches are
+/// still found; the search length only shrinks down to the minimum match length.
+const GST_INITIAL_SEARCH_LENGTH: usize = 20;
+
+/// Candidate match between unmarked tokens, as found by one scan of [`greedy_string_tiling`].
+struct GstMatch {
+    start_a: usize,
+    start_b: usize,
+    length: usize,
+}
+
+/// Maximal runs of unmarked tokens at least `length` long, as (start, end) ranges.
+fn unmarked_runs(marked: &[bool], length: usize) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &is_marked) in marked.iter().chain(std::iter::once(&true)).enumerate() {
+        match (is_marked, start) {
+            (false, None) => start = Some(i),
+            (true, Some(run_start)) => {
+                if i - run_start >= length {
+                    runs.push((run_start, i));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Finds matches of at least `search_length` unmarked tokens, extending each as far as it
+/// goes. Returns the matches and the length of the longest one.
+fn scan_gst_matches(
+    tokens_a: &[u64],
+    tokens_b: &[u64],
+    marked_a: &[bool],
+    marked_b: &[bool],
+    search_length: usize,
+) -> (Vec<GstMatch>, usize) {
+    let mut windows_b: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+    for (start, end) in unmarked_runs(marked_b, search_length) {
+        for (offset, hash) in rolling_hashes(&tokens_b[start..end], search_length)
+            .into_iter()
+            .enumerate()
+        {
+            windows_b.entry(hash).or_default().push(start + offset);
+        }
+    }
+
+    let mut matches = Vec::new();
+    let mut longest = 0;
+    for (start, end) in unmarked_runs(marked_a, search_length) {
+        let hashes_a = rolling_hashes(&tokens_a[start..end], search_length);
+        for (offset, hash) in hashes_a.into_iter().enumerate() {
+            let Some(candidates) = windows_b.get(&hash) else {
+                continue;
+            };
+            let start_a = start + offset;
+            for &start_b in candidates {
+                // A match that extends backwards was already found from its true start.
+                let extends_backwards = start_a > start
+                    && start_b > 0
+                    && !marked_b[start_b - 1]
+                    && tokens_a[start_a - 1] == tokens_b[start_b - 1];
+                if extends_backwards {
+                    continue;
+                }
+                let mut length = 0;
+                while start_a + length < tokens_a.len()
+                    && start_b + length < tokens_b.len()
+                    && !marked_a[start_a + length]
+                    && !marked_b[start_b + length]
+                    && tokens_a[start_a + length] == tokens_b[start_b + length]
+                {
+                    length += 1;
+                }
+                // Shorter runs are hash collisions.
+                if length < search_length {
+                    continue;
+                }
+                longest = longest.max(length);
+                matches.push(GstMatch {
+                    start_a,
+                    start_b,
+                    length,
+                });
+            }
+        }
+    }
+    (matches, longest)
+}
+
+/// Running Karp-Rabin Greedy String Tiling (Wise, 1993).
+///
+/// Repeatedly takes the longest common runs of tokens not yet covered by a tile in
+/// either sequence and turns them into tiles, until no common run of at least
+/// `min_match_length` uncovered tokens is left. Tiles never overlap, so moved or reordered
+/// blocks are each matched once.
+pub fn greedy_string_tiling(
+    tokens_a: &[u64],
+    tokens_b: &[u64],
+    min_match_length: usize,
+) -> Vec<Tile> {
+    greedy_string_tiling_unmarked(
+        tokens_a,
+        tokens_b,
+        min_match_length,
+        vec![false; tokens_a.len()],
+        vec![false; tokens_b.len()],
+    )
+}
+
+/// Same as [`greedy_string_tiling`], but tokens already marked in `marked_a` or
+/// `marked_b` can never be part of a tile.
+fn greedy_string_tiling_unmarked(
+    tokens_a: &[u64],
+    tokens_b: &[u64],
+    min_match_length: usize,
+    mut marked_a: Vec<bool>,
+    mut marked_b: Vec<bool>,
+) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    if min_match_length == 0 {
+        return tiles;
+    }
+    let mut search_length = GST_INITIAL_SEARCH_LENGTH.max(min_match_length);
+
+    loop {
+        let (mut matches, longest) =
+            scan_gst_matches(tokens_a, tokens_b, &marked_a, &marked_b, search_length);
+        if longest > 2 * search_length {
+            // Much longer matches exist: search for them directly so that shorter ones
+            // cannot claim their tokens first.
+            search_length = longest;
+            continue;
+        }
+
+        matches.sort_by_key(|m| (std::cmp::Reverse(m.length), m.start_a, m.start_b));
+        let tiles_before = tiles.len();
+        for m in matches {
+            let occluded =
+                (0..m.length).any(|i| marked_a[m.start_a + i] || marked_b[m.start_b + i]);
+            if occluded {
+                continue;
+            }
+            marked_a[m.start_a..m.start_a + m.length].fill(true);
+            marked_b[m.start_b..m.start_b + m.length].fill(true);
+            tiles.push(Tile {
+                start_a: m.start_a,
+                start_b: m.start_b,
+                length: m.length,
+            });
+        }
+
+        if search_length > 2 * min_match_length {
+            search_length /= 2;
+        } else if search_length > min_match_length {
+            search_length = min_match_length;
+        } else if tiles.len() == tiles_before {
+            break;
+        }
+        // Otherwise rescan at the minimum length: matches cut short by the tiles just
+        // laid may still leave runs long enough to tile.
     }
+
+    tiles.sort_by_key(|tile| tile.start_a);
+    tiles
+}
+
+/// Marks the tokens covered by any `length`-token k-gram whose hash is in `excluded`.
+fn excluded_tokens(tokens: &[u64], length: usize, excluded: &FxHashSet<u64>) -> Vec<bool> {
+    let mut marked = vec![false; tokens.len()];
+    if excluded.is_empty() {
+        return marked;
+    }
+    for (start, hash) in rolling_hashes(tokens, length).into_iter().enumerate() {
+        if excluded.contains(&hash) {
+            marked[start..start + length].fill(true);
+        }
+    }
+    marked
+}
+
+/// Compares two documents with Greedy String Tiling over their renaming-resistant token
+/// streams, so the score also reflects reordered and renamed code.
+///
+/// `excluded` holds hashes of `min_match_length`-token k-grams (as computed by
+/// [`generate_token_kgram_hashes`] over renaming-normalized tokens) whose tokens may not
+/// be tiled and do not count toward the coverage.
+pub fn compare_documents_gst(
+    doc1: &str,
+    doc2: &str,
+    tokenizer: &dyn Tokenizer,
+    min_match_length: usize,
+    excluded: &FxHashSet<u64>,
+) -> GstResult {
+    let token_ids = |doc: &str| -> (Vec<u64>, Vec<(usize, usize)>) {
+        tokenizer
+            .tokenize(doc)
+            .iter()
+            .map(|token| {
+                (
+                    token_id(token.normalized_text(TokenNormalization::Renaming)),
+                    token.span,
+                )
+            })
+            .unzip()
+    };
+    let (tokens1, spans1) = token_ids(doc1);
+    let (tokens2, spans2) = token_ids(doc2);
+
+    let excluded1 = excluded_tokens(&tokens1, min_match_length, excluded);
+    let excluded2 = excluded_tokens(&tokens2, min_match_length, excluded);
+    let total = [&excluded1, &excluded2]
+        .into_iter()
+        .flatten()
+        .filter(|&&is_excluded| !is_excluded)
+        .count();
+    let tiles =
+        greedy_string_tiling_unmarked(&tokens1, &tokens2, min_match_length, excluded1, excluded2);
+    let covered: usize = tiles.iter().map(|tile| tile.length).sum();
+    let similarity = if total == 0 {
+        0.0
+    } else {
+        2.0 * covered as f64 / total as f64
+    };
+
+    // Each tile becomes its own region: pairs are keyed by tile start, which is unique
+    // in both documents, and a gap of zero keeps tiles from being chained.
+    let tile_lengths: FxHashMap<usize, usize> = tiles
+        .iter()
+        .map(|tile| (tile.start_a, tile.length))
+        .collect();
+    let tile_lengths_b: FxHashMap<usize, usize> = tiles
+        .iter()
+        .map(|tile| (tile.start_b, tile.length))
+        .collect();
+    let pairs: Vec<(usize, usize)> = tiles
+        .iter()
+        .map(|tile| (tile.start_a, tile.start_b))
+        .collect();
+    let tile_span = |spans: &[(usize, usize)], lengths: &FxHashMap<usize, usize>, i: usize| {
+        (spans[i].0, spans[i + lengths[&i] - 1].1)
+    };
+    let match_regions = build_match_regions(
+        doc1,
+        doc2,
+        &pairs,
+        |i| tile_span(&spans1, &tile_lengths, i),
+        |i| tile_span(&spans2, &tile_lengths_b, i),
+        0,
+    );
+
+    GstResult {
+        similarity,
+        tiles,
+        tokens_doc1: tokens1.len(),
+        tokens_doc2: tokens2.len(),
+        match_regions,
+    }
+}
+
+/// Pairs rows with columns of `weights` so that the total weight of the pairs is
+/// maximal, each row and each column being used at most once (Hungarian algorithm).
+/// Returns the column paired with each row; rows are only paired through positive
+/// weights. Ties are broken the same way on every run, so equal inputs always give
+/// equal assignments.
+pub fn max_weight_assignment(weights: &[Vec<f64>]) -> Vec<Option<usize>> {
+    let mut assignment = vec![None; weights.len()];
+    // Rows and columns without a single positive weight are never paired; leaving them
+    // out keeps the cubic algorithm fast on sparse score matrices.
+    let rows: Vec<usize> = (0..weights.len())
+        .filter(|&row| weights[row].iter().any(|&weight| weight > 0.0))
+        .collect();
+    let mut columns: Vec<usize> = rows
+        .iter()
+        .flat_map(|&row| {
+            weights[row]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &weight)| weight > 0.0)
+                .map(|(column, _)| column)
+        })
+        .collect();
+    columns.sort_unstable();
+    columns.dedup();
+    if rows.is_empty() {
+        return assignment;
+    }
+
+    // The algorithm below assigns every one of its `n` rows to one of its `m >= n`
+    // columns at minimal cost, so the smaller side plays the rows.
+    let transposed = rows.len() > columns.len();
+    let (n, m) = if transposed {
+        (columns.len(), rows.len())
+    } else {
+        (rows.len(), columns.len())
+    };
+    let cost = |i: usize, j: usize| {
+        let (row, column) = if transposed {
+            (rows[j - 1], columns[i - 1])
+        } else {
+            (rows[i - 1], columns[j - 1])
+        };
+        -weights[row][column]
+    };
+
+    // Potentials, 1-based with index 0 as a sentinel; `matched_row[j]` is the row
+    // assigned to column `j`.
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut matched_row = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+    for i in 1..=n {
+        matched_row[0] = i;
+        let mut j0 = 0;
+        let mut min_slack = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = matched_row[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let slack = cost(i0, j) - u[i0] - v[j];
+                if slack < min_slack[j] {
+                    min_slack[j] = slack;
+                    way[j] = j0;
+                }
+                if min_slack[j] < delta {
+                    delta = min_slack[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[matched_row[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_slack[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if matched_row[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            matched_row[j0] = matched_row[j1];
+            j0 = j1;
+        }
+    }
+
+    for j in 1..=m {
+        let i = matched_row[j];
+        if i == 0 {
+            continue;
+        }
+        let (row, column) = if transposed {
+            (rows[j - 1], columns[i - 1])
+        } else {
+            (rows[i - 1], columns[j - 1])
+        };
+        if weights[row][column] > 0.0 {
+            assignment[row] = Some(column);
+        }
+    }
+    assignment
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tokenizer::TextTokenizer;
     use rustc_hash::FxHashSet;
 
     #[test]
@@ -321,34 +1153,64 @@ mod tests {
     #[test]
     fn test_moss_hash_token_kgram_empty() {
         let empty_kgram: Vec<String> = vec![];
-        let expected_hash = {
-            let mut hasher = DefaultHasher::new();
-            empty_kgram.hash(&mut hasher);
-            hasher.finish()
-        };
-        assert_eq!(hash_token_kgram(&empty_kgram), expected_hash);
+        assert_eq!(hash_token_kgram(&empty_kgram), 0xcda3_149b_bdc3_e4cf);
     }
 
     #[test]
     fn test_moss_hash_token_kgram_single_token() {
         let kgram = vec!["hello".to_string()];
-        let expected_hash = {
-            let mut hasher = DefaultHasher::new();
-            kgram.hash(&mut hasher);
-            hasher.finish()
-        };
-        assert_eq!(hash_token_kgram(&kgram), expected_hash);
+        assert_eq!(hash_token_kgram(&kgram), 0x918c_9662_9b53_c452);
     }
 
     #[test]
     fn test_moss_hash_token_kgram_multiple_tokens() {
         let kgram = vec!["the".to_string(), "quick".to_string(), "brown".to_string()];
-        let expected_hash = {
-            let mut hasher = DefaultHasher::new();
-            kgram.hash(&mut hasher);
-            hasher.finish()
-        };
-        assert_eq!(hash_token_kgram(&kgram), expected_hash);
+        assert_eq!(hash_token_kgram(&kgram), 0xf8d6_eb78_fba7_763a);
+    }
+
+    #[test]
+    fn test_moss_hash_token_kgram_token_boundaries_matter() {
+        let split_early = vec!["ab".to_string(), "c".to_string()];
+        let split_late = vec!["a".to_string(), "bc".to_string()];
+        assert_ne!(
+            hash_token_kgram(&split_early),
+            hash_token_kgram(&split_late)
+        );
+    }
+
+    // Golden values below were computed with an independent implementation of the
+    // documented scheme. If one of these tests fails, persisted fingerprints are no
+    // longer comparable: bump FINGERPRINT_VERSION along with the new values.
+
+    #[test]
+    fn test_stable_hash_golden_values() {
+        assert_eq!(FINGERPRINT_VERSION, 1);
+        assert_eq!(stable_hash(b""), 0xcda3_149b_bdc3_e4cf);
+        assert_eq!(stable_hash(b"hello"), 0xae7d_a6fb_3bb4_cec2);
+        assert_eq!(stable_hash(b"int main(void)"), 0x89fb_8de9_fcf3_92cb);
+    }
+
+    #[test]
+    fn test_rolling_byte_hashes_golden_values() {
+        assert_eq!(
+            rolling_hashes(b"int main", 4),
+            vec![
+                0x1b7d_7565_f1f2_b2b1,
+                0x0d91_d7c2_e4c4_6ef7,
+                0x08f9_f614_645c_bf49,
+                0x1cdf_d938_3d25_2630,
+                0x1a3f_a0fa_9488_f63c,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_kgram_hashes_golden_values() {
+        let tokens = tokenize("int main(void) return 0");
+        assert_eq!(
+            generate_token_kgram_hashes(&tokens, 4),
+            vec![(0x09c6_63e8_a95f_a6b3, 0), (0x0d6b_d1fa_9d86_002f, 1)]
+        );
     }
 
     #[test]
@@ -378,30 +1240,63 @@ mod tests {
         );
     }
 
+    /// Straightforward O(n * w) robust winnowing used as a reference implementation.
+    fn naive_robust_winnow(hashes: &[(u64, usize)], window_size: usize) -> Vec<(u64, usize)> {
+        let mut fingerprints = Vec::new();
+        if window_size == 0 || hashes.is_empty() {
+            return fingerprints;
+        }
+        let window_size = window_size.min(hashes.len());
+        let mut selected: Option<usize> = None;
+        for start in 0..=(hashes.len() - window_size) {
+            let window = start..start + window_size;
+            let minimum = window.clone().map(|i| hashes[i].0).min().unwrap();
+            if selected.is_some_and(|p| window.contains(&p) && hashes[p].0 == minimum) {
+                continue;
+            }
+            let rightmost = window.rev().find(|&i| hashes[i].0 == minimum).unwrap();
+            selected = Some(rightmost);
+            fingerprints.push(hashes[rightmost]);
+        }
+        fingerprints
+    }
+
+    /// Deterministic pseudo-random hashes drawn from a small alphabet to force ties.
+    fn pseudo_random_hashes(len: usize, alphabet: u64, seed: u64) -> Vec<(u64, usize)> {
+        let mut state = seed;
+        (0..len)
+            .map(|i| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                ((state >> 33) % alphabet, i)
+            })
+            .collect()
+    }
+
     #[test]
     fn test_moss_winnow_empty_hashes() {
         let hashes: Vec<(u64, usize)> = vec![];
-        assert_eq!(winnow_hashes(&hashes, 4), HashSet::new());
+        assert_eq!(winnow_hashes(&hashes, 4), Vec::new());
     }
 
     #[test]
     fn test_moss_winnow_window_size_zero() {
         let hashes = vec![(10, 0), (20, 1), (5, 2), (30, 3)];
 
-        assert_eq!(winnow_hashes(&hashes, 0), HashSet::new());
+        assert_eq!(winnow_hashes(&hashes, 0), Vec::new());
     }
 
     #[test]
     fn test_moss_winnow_hashes_less_than_window_size() {
         let hashes = vec![(10, 0), (20, 1), (5, 2)];
-        let mut expected = HashSet::new();
-        expected.insert((5, 2));
-        assert_eq!(winnow_hashes(&hashes, 4), expected);
+        assert_eq!(winnow_hashes(&hashes, 4), vec![(5, 2)]);
 
         let hashes_single = vec![(100, 0)];
-        let mut expected_single = HashSet::new();
-        expected_single.insert((100, 0));
-        assert_eq!(winnow_hashes(&hashes_single, 4), expected_single);
+        assert_eq!(winnow_hashes(&hashes_single, 4), vec![(100, 0)]);
+
+        let tied = vec![(7, 0), (3, 1), (3, 2)];
+        assert_eq!(winnow_hashes(&tied, 4), vec![(3, 2)]);
     }
 
     #[test]
@@ -419,10 +1314,9 @@ mod tests {
             (69, 9),
         ];
         let w = 4;
-        let expected_fingerprints = [(17, 3), (12, 5), (12, 6), (5, 8)]
-            .iter()
-            .cloned()
-            .collect::<HashSet<_>>();
+        // Robust winnowing keeps (12, 5) while it stays the window minimum, so the tied
+        // (12, 6) is never recorded.
+        let expected_fingerprints = vec![(17, 3), (12, 5), (5, 8)];
         assert_eq!(winnow_hashes(&hashes, w), expected_fingerprints);
     }
 
@@ -431,11 +1325,47 @@ mod tests {
         let hashes = vec![(5, 0), (5, 1), (5, 2), (5, 3), (5, 4)];
         let w = 3;
 
-        let mut expected = HashSet::new();
-        expected.insert((5, 2));
-        expected.insert((5, 3));
-        expected.insert((5, 4));
-        assert_eq!(winnow_hashes(&hashes, w), expected);
+        // The first window selects its rightmost minimum, which then stays selected.
+        assert_eq!(winnow_hashes(&hashes, w), vec![(5, 2)]);
+
+        let longer: Vec<(u64, usize)> = (0..8).map(|i| (5, i)).collect();
+        assert_eq!(winnow_hashes(&longer, w), vec![(5, 2), (5, 5)]);
+    }
+
+    #[test]
+    fn test_moss_winnow_output_is_ordered_by_position() {
+        let hashes = pseudo_random_hashes(500, 1_000, 7);
+        let fingerprints = winnow_hashes(&hashes, 6);
+        assert!(fingerprints.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    fn test_moss_winnow_selects_one_fingerprint_per_window() {
+        let hashes = pseudo_random_hashes(300, 50, 11);
+        let w = 5;
+        let fingerprints = winnow_hashes(&hashes, w);
+        for start in 0..=(hashes.len() - w) {
+            assert!(
+                fingerprints
+                    .iter()
+                    .any(|&(_, p)| (start..start + w).contains(&p)),
+                "window starting at {start} has no fingerprint"
+            );
+        }
+    }
+
+    #[test]
+    fn test_moss_winnow_matches_naive_implementation() {
+        for (alphabet, seed) in [(2, 1), (3, 2), (10, 3), (1_000_000, 4)] {
+            let hashes = pseudo_random_hashes(400, alphabet, seed);
+            for w in [1, 2, 3, 4, 8, 17, 400, 500] {
+                assert_eq!(
+                    winnow_hashes(&hashes, w),
+                    naive_robust_winnow(&hashes, w),
+                    "alphabet {alphabet}, window {w}"
+                );
+            }
+        }
     }
 
     #[test]
@@ -498,13 +1428,56 @@ mod tests {
         assert!((calculate_jaccard_index(&set_a, &set_b) - expected).abs() < 1e-9);
     }
 
+    #[test]
+    fn test_containment_is_asymmetric() {
+        let small: FxHashSet<u64> = FxHashSet::from_iter([1, 2, 3]);
+        let large: FxHashSet<u64> = FxHashSet::from_iter(1..=30);
+
+        assert_eq!(calculate_containment(&small, &large), 1.0);
+        assert!((calculate_containment(&large, &small) - 0.1).abs() < 1e-9);
+        assert!((calculate_jaccard_index(&small, &large) - 0.1).abs() < 1e-9);
+        assert_eq!(calculate_containment(&FxHashSet::default(), &large), 0.0);
+    }
+
+    #[test]
+    fn test_compare_documents_moss_like_reports_containment_of_copied_file() {
+        use crate::tokenizer::CTokenizer;
+
+        let copied = "int square(int x) {\n    return x * x;\n}\n";
+        let own_code: String = (0..20)
+            .map(|i| format!("int helper_{i}(int v) {{\n    return v + {i} * v - {i};\n}}\n"))
+            .collect();
+        let project = format!("{own_code}{copied}");
+        let result = compare_documents_moss_like(
+            copied,
+            &project,
+            &CTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
+
+        assert_eq!(result.containment_doc1, 1.0);
+        assert!(result.containment_doc2 < 0.2);
+        assert!(result.score < 0.2);
+    }
+
     #[test]
     fn test_compare_documents_moss_like_identical_simple() {
         let doc1 = "The quick brown fox jumps over the lazy dog.";
         let doc2 = "The quick brown fox jumps over the lazy dog.";
         let _k = 4;
         let _w = 5;
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result.score, 1.0);
         assert!(result.fingerprints_doc1 > 0);
         assert_eq!(result.fingerprints_doc1, result.fingerprints_doc2);
@@ -517,7 +1490,15 @@ mod tests {
         let doc2 = "Another document, completely unrelated content here.";
         let _k = 4;
         let _w = 5;
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert!(
             result.score < 1.0,
             "Expected very low score for different docs, got {}",
@@ -536,7 +1517,15 @@ mod tests {
         let doc2 = "";
         let _k = 4;
         let _w = 5;
-        let result1 = compare_documents_moss_like(doc1, doc2);
+        let result1 = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result1.score, 0.0);
         assert!(
             result1.fingerprints_doc1 > 0,
@@ -545,7 +1534,15 @@ mod tests {
         assert_eq!(result1.fingerprints_doc2, 0);
         assert_eq!(result1.fingerprints_matched, 0);
 
-        let result2 = compare_documents_moss_like(doc2, doc1);
+        let result2 = compare_documents_moss_like(
+            doc2,
+            doc1,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result2.score, 0.0);
         assert_eq!(result2.fingerprints_doc1, 0);
         assert!(
@@ -561,7 +1558,15 @@ mod tests {
         let doc2 = "";
         let _k = 4;
         let _w = 5;
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result.score, 1.0);
         assert_eq!(result.fingerprints_doc1, 0);
         assert_eq!(result.fingerprints_doc2, 0);
@@ -574,7 +1579,15 @@ mod tests {
         let doc2 = "hi";
         let _k = 4;
         let _w = 5;
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
 
         assert_eq!(result.score, 1.0);
         assert_eq!(result.fingerprints_doc1, 0);
@@ -582,7 +1595,15 @@ mod tests {
         assert_eq!(result.fingerprints_matched, 0);
 
         let doc3 = "bye";
-        let result2 = compare_documents_moss_like(doc1, doc3);
+        let result2 = compare_documents_moss_like(
+            doc1,
+            doc3,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result2.score, 1.0);
         assert_eq!(result2.fingerprints_doc1, 0);
         assert_eq!(result2.fingerprints_doc2, 0);
@@ -595,7 +1616,15 @@ mod tests {
         let doc2 = "word word word word";
         let _k = 4;
         let _w = 1;
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result.score, 1.0);
         assert_eq!(
             result.fingerprints_doc1, 1,
@@ -608,7 +1637,15 @@ mod tests {
         assert_eq!(result.fingerprints_matched, 1);
 
         let doc3 = "diff diff diff diff";
-        let result2 = compare_documents_moss_like(doc1, doc3);
+        let result2 = compare_documents_moss_like(
+            doc1,
+            doc3,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result2.score, 0.0);
         assert_eq!(result2.fingerprints_doc1, 1);
         assert_eq!(result2.fingerprints_doc2, 1);
@@ -619,7 +1656,411 @@ mod tests {
     fn test_debug_specific_concatenated_strings() {
         let doc1 = "fn main() { println!(\"Hello, world!\"); }";
         let doc2 = "fn main() { println!(\"Hello, world!\"); }";
-        let result = compare_documents_moss_like(doc1, doc2);
+        let result = compare_documents_moss_like(
+            doc1,
+            doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
         assert_eq!(result.score, 1.0);
     }
+
+    #[test]
+    fn test_tokenize_with_spans_points_into_original_text() {
+        let text = "Foo(bar_Baz) + 42";
+        let tokens = tokenize_with_spans(text);
+        let expected = vec![
+            ("foo".to_string(), (0, 3)),
+            ("bar".to_string(), (4, 7)),
+            ("baz".to_string(), (8, 11)),
+            ("42".to_string(), (15, 17)),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_pair_shared_fingerprints_ignores_unshared_hashes() {
+        let a = vec![(1, 0), (2, 3), (3, 7)];
+        let b = vec![(3, 1), (1, 4), (9, 6)];
+        assert_eq!(pair_shared_fingerprints(&a, &b), vec![(0, 4), (7, 1)]);
+    }
+
+    #[test]
+    fn test_build_match_regions_chains_consecutive_pairs() {
+        let doc_a = "abc\ndef\nghi\n";
+        let doc_b = "xx\nabc\ndef\n";
+        // Byte k-grams of length 3 starting at the given offsets.
+        let pairs = vec![(0, 3), (4, 7)];
+        let regions = build_match_regions(doc_a, doc_b, &pairs, |i| (i, i + 3), |i| (i, i + 3), 4);
+        assert_eq!(
+            regions,
+            vec![MatchRegion {
+                start_line_a: 1,
+                start_column_a: 1,
+                end_line_a: 2,
+                end_column_a: 3,
+                start_line_b: 2,
+                start_column_b: 1,
+                end_line_b: 3,
+                end_column_b: 3,
+                shared_fingerprints: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_build_match_regions_splits_on_large_gaps() {
+        let doc = "0123456789abcdefghij";
+        let pairs = vec![(0, 0), (1, 1), (15, 2)];
+        let regions = build_match_regions(doc, doc, &pairs, |i| (i, i + 1), |i| (i, i + 1), 2);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].shared_fingerprints, 2);
+        assert_eq!(
+            (regions[1].start_column_a, regions[1].start_column_b),
+            (16, 3)
+        );
+    }
+
+    #[test]
+    fn test_merge_match_regions_joins_overlaps() {
+        let region = |start_a: usize, end_a: usize, start_b: usize, end_b: usize| MatchRegion {
+            start_line_a: start_a,
+            start_column_a: 1,
+            end_line_a: end_a,
+            end_column_a: 1,
+            start_line_b: start_b,
+            start_column_b: 1,
+            end_line_b: end_b,
+            end_column_b: 1,
+            shared_fingerprints: 1,
+        };
+        let merged = merge_match_regions(vec![
+            region(5, 9, 1, 4),
+            region(1, 6, 1, 2),
+            region(20, 25, 30, 35),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].start_line_a, merged[0].end_line_a), (1, 9));
+        assert_eq!((merged[0].start_line_b, merged[0].end_line_b), (1, 4));
+        assert_eq!(merged[0].shared_fingerprints, 2);
+    }
+
+    #[test]
+    fn test_compare_documents_moss_like_reports_copied_lines() {
+        let shared = "int total = 0;\nfor (int i = 0; i < n; i++) {\n    total += values[i] * weights[i];\n}\nreturn total;\n";
+        let doc1 = format!("#include <stdio.h>\n\n{shared}");
+        let doc2 = format!("// my own header comment here\n{shared}\nputs(\"done\");\n");
+        let result = compare_documents_moss_like(
+            &doc1,
+            &doc2,
+            &TextTokenizer,
+            TokenNormalization::Raw,
+            DEFAULT_MOSS_K,
+            DEFAULT_MOSS_WINDOW,
+            &FxHashSet::default(),
+        );
+
+        assert!(!result.match_regions.is_empty());
+        let region = &result.match_regions[0];
+        assert!(region.start_line_a >= 3 && region.start_line_b >= 2);
+        assert_eq!(region.start_line_a - region.start_line_b, 1);
+        assert_eq!(region.end_line_a - region.end_line_b, 1);
+        assert!(region.end_line_a > region.start_line_a + 1);
+        assert_eq!(region.shared_fingerprints, result.fingerprints_matched);
+    }
+
+    #[test]
+    fn test_compare_documents_moss_like_renaming_normalization_sees_through_renames() {
+        use crate::tokenizer::CTokenizer;
+
+        let original = "int sum(int *values, int n) {\n    int total = 0;\n    for (int i = 0; i < n; i++) {\n        total += values[i];\n    }\n    printf(\"total: %d\\n\", total);\n    return total;\n}\n";
+        let renamed = "int add_all(int *xs, int len) {\n    int acc = 0;\n    for (int j = 0; j < len; j++) {\n        acc += xs[j];\n    }\n    printf(\"sum = %d\\n\", acc);\n    return acc;\n}\n";
+        let compare = |normalization| {
+            compare_documents_moss_like(
+                original,
+                renamed,
+                &CTokenizer,
+                normalization,
+                DEFAULT_MOSS_K,
+                DEFAULT_MOSS_WINDOW,
+                &FxHashSet::default(),
+            )
+        };
+
+        let raw = compare(TokenNormalization::Raw);
+        let normalized = compare(TokenNormalization::Renaming);
+        assert!(raw.score < 0.5, "raw score {} should be low", raw.score);
+        assert_eq!(normalized.score, 1.0);
+        assert!(!normalized.match_regions.is_empty());
+    }
+
+    /// Longest run of equal tokens starting at `a` and `b` that no tile covers.
+    fn uncovered_run(
+        tokens_a: &[u64],
+        tokens_b: &[u64],
+        tiles: &[Tile],
+        a: usize,
+        b: usize,
+    ) -> usize {
+        let covered_a = |i: usize| {
+            tiles
+                .iter()
+                .any(|t| (t.start_a..t.start_a + t.length).contains(&i))
+        };
+        let covered_b = |i: usize| {
+            tiles
+                .iter()
+                .any(|t| (t.start_b..t.start_b + t.length).contains(&i))
+        };
+        let mut length = 0;
+        while a + length < tokens_a.len()
+            && b + length < tokens_b.len()
+            && tokens_a[a + length] == tokens_b[b + length]
+            && !covered_a(a + length)
+            && !covered_b(b + length)
+        {
+            length += 1;
+        }
+        length
+    }
+
+    #[test]
+    fn test_greedy_string_tiling_identical_sequences_form_one_tile() {
+        let tokens: Vec<u64> = (0..50).collect();
+        assert_eq!(
+            greedy_string_tiling(&tokens, &tokens, 5),
+            vec![Tile {
+                start_a: 0,
+                start_b: 0,
+                length: 50
+            }]
+        );
+    }
+
+    #[test]
+    fn test_greedy_string_tiling_matches_reordered_blocks() {
+        let first: Vec<u64> = (0..15).collect();
+        let second: Vec<u64> = (100..130).collect();
+        let tokens_a = [first.clone(), second.clone()].concat();
+        let tokens_b = [vec![999], second, first].concat();
+
+        let tiles = greedy_string_tiling(&tokens_a, &tokens_b, 5);
+        assert_eq!(
+            tiles,
+            vec![
+                Tile {
+                    start_a: 0,
+                    start_b: 31,
+                    length: 15
+                },
+                Tile {
+                    start_a: 15,
+                    start_b: 1,
+                    length: 30
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_greedy_string_tiling_ignores_matches_below_minimum_length() {
+        let tokens_a = [1, 2, 3, 4, 9, 9, 9, 9];
+        let tokens_b = [7, 1, 2, 3, 4, 7, 7, 7];
+        assert!(greedy_string_tiling(&tokens_a, &tokens_b, 5).is_empty());
+        assert_eq!(greedy_string_tiling(&tokens_a, &tokens_b, 4).len(), 1);
+        assert!(greedy_string_tiling(&tokens_a, &tokens_b, 0).is_empty());
+    }
+
+    #[test]
+    fn test_greedy_string_tiling_tiles_are_disjoint_equal_and_maximal() {
+        for (alphabet, seed) in [(2, 5), (4, 6), (30, 7)] {
+            let tokens_a: Vec<u64> = pseudo_random_hashes(300, alphabet, seed)
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect();
+            let tokens_b: Vec<u64> = pseudo_random_hashes(250, alphabet, seed + 100)
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect();
+            let min = 6;
+            let tiles = greedy_string_tiling(&tokens_a, &tokens_b, min);
+
+            let mut covered_a = vec![false; tokens_a.len()];
+            let mut covered_b = vec![false; tokens_b.len()];
+            for tile in &tiles {
+                assert!(tile.length >= min);
+                assert_eq!(
+                    tokens_a[tile.start_a..tile.start_a + tile.length],
+                    tokens_b[tile.start_b..tile.start_b + tile.length]
+                );
+                for i in 0..tile.length {
+                    assert!(!covered_a[tile.start_a + i] && !covered_b[tile.start_b + i]);
+                    covered_a[tile.start_a + i] = true;
+                    covered_b[tile.start_b + i] = true;
+                }
+            }
+            for a in 0..tokens_a.len() {
+                for b in 0..tokens_b.len() {
+                    assert!(
+                        uncovered_run(&tokens_a, &tokens_b, &tiles, a, b) < min,
+                        "uncovered common run at ({a}, {b}), alphabet {alphabet}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare_documents_gst_scores_reordered_and_renamed_code() {
+        use crate::tokenizer::PythonTokenizer;
+
+        let doc1 = "def area(w, h):\n    result = w * h\n    return result\n\ndef perimeter(w, h):\n    total = 2 * (w + h)\n    return total\n";
+        let doc2 = "def perim(a, b):\n    p = 2 * (a + b)\n    return p\n\ndef surface(a, b):\n    s = a * b\n    return s\n";
+        let result = compare_documents_gst(doc1, doc2, &PythonTokenizer, 9, &FxHashSet::default());
+
+        assert_eq!(result.similarity, 1.0);
+        assert_eq!(result.tiles.len(), 2);
+        assert_eq!(result.tokens_doc1, result.tokens_doc2);
+        assert_eq!(result.match_regions.len(), 2);
+        let first = result
+            .match_regions
+            .iter()
+            .find(|region| region.start_line_a == 1)
+            .unwrap();
+        assert_eq!(
+            (first.start_line_b, first.end_line_a, first.end_line_b),
+            (5, 3, 7)
+        );
+
+        let unrelated = compare_documents_gst(
+            doc1,
+            "import os\nprint(os.getcwd())\n",
+            &PythonTokenizer,
+            9,
+            &FxHashSet::default(),
+        );
+        assert_eq!(unrelated.similarity, 0.0);
+        assert!(unrelated.match_regions.is_empty());
+    }
+
+    fn naive_polynomial_hash(window: &[u64]) -> u64 {
+        window.iter().fold(0, |hash, &value| {
+            ((hash as u128 * ROLLING_HASH_BASE as u128 + (value % ROLLING_HASH_MODULUS) as u128)
+                % ROLLING_HASH_MODULUS as u128) as u64
+        })
+    }
+
+    #[test]
+    fn test_rolling_hashes_match_direct_polynomial_hash() {
+        let values: Vec<u64> = (0..200u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (i << 7))
+            .collect();
+        for k in [1, 2, 5, 25] {
+            let expected: Vec<u64> = values.windows(k).map(naive_polynomial_hash).collect();
+            assert_eq!(rolling_hashes(&values, k), expected, "k = {k}");
+        }
+    }
+
+    #[test]
+    fn test_rolling_hashes_edge_cases() {
+        assert!(rolling_hashes(b"abc", 0).is_empty());
+        assert!(rolling_hashes(b"abc", 4).is_empty());
+        assert_eq!(rolling_hashes(b"abc", 3).len(), 1);
+        assert_eq!(rolling_hashes(&[u64::MAX, 7], 2).len(), 1);
+    }
+
+    #[test]
+    fn test_byte_kgrams_equal_windows_hash_equally() {
+        let kgrams = generate_byte_kgrams(b"abcXabcYabc", 3);
+        let positions: Vec<usize> = kgrams.iter().map(|&(_, position)| position).collect();
+        assert_eq!(positions, (0..9).collect::<Vec<_>>());
+        let hash_at = |position: usize| kgrams[position].0;
+        assert_eq!(hash_at(0), hash_at(4));
+        assert_eq!(hash_at(0), hash_at(8));
+        assert_ne!(hash_at(0), hash_at(1));
+    }
+
+    #[test]
+    fn test_token_kgram_hashes_follow_token_windows() {
+        let tokens = tokenize("a b c a b c d");
+        let hashes = generate_token_kgram_hashes(&tokens, 3);
+        assert_eq!(hashes.len(), 5);
+        assert_eq!(hashes[0].0, hashes[3].0);
+        assert_ne!(hashes[0].0, hashes[1].0);
+        assert_eq!(
+            hashes.iter().map(|&(_, i)| i).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+
+    /// Best total weight over every one-to-one pairing, by exhaustive search.
+    fn best_total_weight(weights: &[Vec<f64>], row: usize, used: &mut Vec<bool>) -> f64 {
+        if row == weights.len() {
+            return 0.0;
+        }
+        let mut best = best_total_weight(weights, row + 1, used);
+        for column in 0..used.len() {
+            if !used[column] && weights[row][column] > 0.0 {
+                used[column] = true;
+                let total = weights[row][column] + best_total_weight(weights, row + 1, used);
+                used[column] = false;
+                best = best.max(total);
+            }
+        }
+        best
+    }
+
+    fn total_weight(weights: &[Vec<f64>], assignment: &[Option<usize>]) -> f64 {
+        assignment
+            .iter()
+            .enumerate()
+            .filter_map(|(row, column)| column.map(|column| weights[row][column]))
+            .sum()
+    }
+
+    #[test]
+    fn test_max_weight_assignment_prefers_total_over_greedy() {
+        // Greedily pairing row 0 with its best column 0 would leave row 1 with nothing.
+        let weights = vec![vec![0.9, 0.8], vec![0.7, 0.0]];
+        assert_eq!(max_weight_assignment(&weights), vec![Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn test_max_weight_assignment_leaves_zero_weights_unpaired() {
+        let weights = vec![vec![0.0, 0.0, 0.0], vec![0.0, 0.5, 0.0]];
+        assert_eq!(max_weight_assignment(&weights), vec![None, Some(1)]);
+        assert_eq!(max_weight_assignment(&[]), Vec::new());
+        assert_eq!(max_weight_assignment(&[vec![], vec![]]), vec![None, None]);
+    }
+
+    #[test]
+    fn test_max_weight_assignment_is_optimal_and_one_to_one() {
+        for (rows, columns) in [(1, 4), (3, 3), (4, 2), (5, 6), (6, 5)] {
+            for seed in 0..20 {
+                let values = pseudo_random_hashes(rows * columns, 6, seed);
+                let weights: Vec<Vec<f64>> = values
+                    .chunks(columns)
+                    .map(|row| row.iter().map(|&(value, _)| value as f64 / 5.0).collect())
+                    .collect();
+
+                let assignment = max_weight_assignment(&weights);
+                let mut paired: Vec<usize> = assignment.iter().flatten().copied().collect();
+                let pair_count = paired.len();
+                paired.sort_unstable();
+                paired.dedup();
+                assert_eq!(paired.len(), pair_count, "a column is paired twice");
+                assert!((0..rows).all(|row| assignment[row].is_none_or(|c| weights[row][c] > 0.0)));
+
+                let optimum = best_total_weight(&weights, 0, &mut vec![false; columns]);
+                assert!(
+                    (total_weight(&weights, &assignment) - optimum).abs() < 1e-9,
+                    "{rows}x{columns} seed {seed}"
+                );
+                assert_eq!(max_weight_assignment(&weights), assignment);
+            }
+        }
+    }
 }
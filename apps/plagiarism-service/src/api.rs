@@ -1,26 +1,156 @@
-use crate::comparison_orchestrator::{ProjectComparisonReport, compare_normalized_projects};
+use crate::algorithm::{MatchRegion, MossResult};
+use crate::clustering::find_clusters;
+use crate::comparison_orchestrator::{
+    ComparisonConfig, ExcludedFingerprints, ProjectComparisonReport, RabinKarpComparisonResult,
+    calculate_combined_score, compare_normalized_projects, compare_normalized_projects_indexed,
+    effective_moss_score, render_comparison_html,
+};
+use crate::extraction::{ExtractionLimits, extract_archive};
+use crate::fingerprint_index::{CandidatePairs, FingerprintIndex};
+use crate::jobs::{
+    JobAcceptedResponse, JobManager, JobStatus, ProgressEvent, SubmissionLookupError,
+    SubmissionPair,
+};
 use crate::project_processor::{NormalizedProject, process_project_folder};
-use crate::s3;
-use actix_web::web::{Data, Json};
-use actix_web::{HttpResponse, Responder};
-use apistos::{ApiComponent, api_operation};
+use crate::storage::{StoreError, SubmissionStore};
+use actix_web::dev;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::{self, Bytes, Data, Json, Path as PathParam};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder};
+use apistos::paths::{MediaType, RequestBody};
+use apistos::reference_or::ReferenceOr;
+use apistos::web::{Scope, get, post, put, resource, scope};
+use apistos::{ApiComponent, InstanceType, Schema, SchemaObject, api_operation};
+use futures_util::future::LocalBoxFuture;
+use futures_util::{StreamExt, stream};
+use rayon::prelude::*;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use zip::ZipArchive;
+
+/// File name of the starter-code archive stored next to the submissions of a step.
+pub const TEMPLATE_ARCHIVE_NAME: &str = "base-code.zip";
+/// Largest starter-code archive accepted by [`register_template`].
+const MAX_TEMPLATE_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+/// Attempts made to download an object before giving up on transient errors.
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 4;
+/// Wait before retrying a failed download, doubled after each further failure.
+const INITIAL_DOWNLOAD_BACKOFF: Duration = Duration::from_millis(100);
 
 #[derive(Clone)]
 pub struct AppState {
-    pub extract_base_path: PathBuf,
+    pub jobs: Arc<JobManager>,
+    /// Object storage the submissions and starter code are stored in, connected once at
+    /// startup and shared with the job manager.
+    pub store: Arc<dyn SubmissionStore>,
 }
 
-#[derive(Deserialize, Serialize, JsonSchema, ApiComponent)]
+#[derive(Deserialize, Serialize, JsonSchema, ApiComponent, Clone)]
 pub struct BodyRequest {
     #[serde(rename = "projectId")]
     pub project_id: String,
     #[serde(rename = "promotionId")]
     pub promotion_id: String,
     pub step: String,
+    #[serde(default)]
+    pub config: ComparisonConfig,
+    /// Earlier promotions of the same project step whose submissions are compared with
+    /// the checked ones, but not with each other. Each id must be alphanumeric.
+    #[serde(rename = "archivedPromotionIds", default)]
+    pub archived_promotion_ids: Vec<String>,
+}
+
+/// Lines of the compared file (A) that match lines of the matched file (B). Lines and
+/// columns are 1-based; end positions are inclusive.
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
+pub struct ApiMatchRegion {
+    #[serde(rename = "startLineA")]
+    pub start_line_a: usize,
+    #[serde(rename = "startColumnA")]
+    pub start_column_a: usize,
+    #[serde(rename = "endLineA")]
+    pub end_line_a: usize,
+    #[serde(rename = "endColumnA")]
+    pub end_column_a: usize,
+    #[serde(rename = "startLineB")]
+    pub start_line_b: usize,
+    #[serde(rename = "startColumnB")]
+    pub start_column_b: usize,
+    #[serde(rename = "endLineB")]
+    pub end_line_b: usize,
+    #[serde(rename = "endColumnB")]
+    pub end_column_b: usize,
+    #[serde(rename = "sharedFingerprints")]
+    pub shared_fingerprints: usize,
+}
+
+impl From<&MatchRegion> for ApiMatchRegion {
+    fn from(region: &MatchRegion) -> Self {
+        Self {
+            start_line_a: region.start_line_a,
+            start_column_a: region.start_column_a,
+            end_line_a: region.end_line_a,
+            end_column_a: region.end_column_a,
+            start_line_b: region.start_line_b,
+            start_column_b: region.start_column_b,
+            end_line_b: region.end_line_b,
+            end_column_b: region.end_column_b,
+            shared_fingerprints: region.shared_fingerprints,
+        }
+    }
+}
+
+/// How much of one side of a comparison is found in the other, in percent. Unlike the
+/// similarity scores, containment is not diluted when the other side is much larger.
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Copy, Debug, PartialEq)]
+pub struct ApiContainment {
+    /// Share of this side's MOSS fingerprints found in the matched side.
+    pub moss: f64,
+    /// Share of the matched side's MOSS fingerprints found in this side.
+    #[serde(rename = "matchedMoss")]
+    pub matched_moss: f64,
+    /// Share of this side's Rabin-Karp k-grams found in the matched side.
+    #[serde(rename = "rabinKarp")]
+    pub rabin_karp: f64,
+    /// Share of the matched side's Rabin-Karp k-grams found in this side.
+    #[serde(rename = "matchedRabinKarp")]
+    pub matched_rabin_karp: f64,
+}
+
+impl ApiContainment {
+    fn new(moss: Option<&MossResult>, rabin_karp: Option<&RabinKarpComparisonResult>) -> Self {
+        Self {
+            moss: moss.map_or(0.0, |r| r.containment_doc1 * 100.0),
+            matched_moss: moss.map_or(0.0, |r| r.containment_doc2 * 100.0),
+            rabin_karp: rabin_karp.map_or(0.0, |r| r.containment_doc1 * 100.0),
+            matched_rabin_karp: rabin_karp.map_or(0.0, |r| r.containment_doc2 * 100.0),
+        }
+    }
+
+    /// The same containment seen from the matched side.
+    fn swapped(self) -> Self {
+        Self {
+            moss: self.matched_moss,
+            matched_moss: self.moss,
+            rabin_karp: self.matched_rabin_karp,
+            matched_rabin_karp: self.rabin_karp,
+        }
+    }
+
+    /// Highest containment in either direction, used by the flagging rules.
+    fn max(&self) -> f64 {
+        self.moss
+            .max(self.matched_moss)
+            .max(self.rabin_karp)
+            .max(self.matched_rabin_karp)
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
@@ -35,11 +165,24 @@ pub struct ApiFileComparisonDetail {
     pub lines_of_code: usize,
     #[serde(rename = "mossScore")]
     pub moss_score: f64,
+    /// MOSS score with identifiers and literals normalized away, present when the check
+    /// was run with `renamingResistant`.
+    #[serde(rename = "normalizedMossScore")]
+    pub normalized_moss_score: Option<f64>,
     #[serde(rename = "rabinKarpScore")]
     pub rabin_karp_score: f64,
+    /// Greedy String Tiling coverage, present when the check was run with
+    /// `greedyStringTiling`.
+    #[serde(rename = "gstScore")]
+    pub gst_score: Option<f64>,
     #[serde(rename = "combinedScore")]
     pub combined_score: f64,
+    pub containment: ApiContainment,
     pub flags: Vec<String>,
+    #[serde(rename = "matchedFileRelativePath")]
+    pub matched_file_relative_path: PathBuf,
+    #[serde(rename = "matchedRegions")]
+    pub matched_regions: Vec<ApiMatchRegion>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
@@ -50,9 +193,23 @@ pub struct ApiMatchDetail {
     pub overall_match_percentage: f64,
     #[serde(rename = "combinedScore")]
     pub combined_score: f64,
+    /// Whole-project containment, from the point of view of the folder this match is
+    /// reported for.
+    pub containment: ApiContainment,
     pub flags: Vec<String>,
+    /// One-to-one matches between the files of both folders.
     #[serde(rename = "fileComparisons")]
     pub file_comparisons: Vec<ApiFileComparisonDetail>,
+    /// Files of this folder matched with no file of the matched folder.
+    #[serde(rename = "unmatchedFiles")]
+    pub unmatched_files: Vec<PathBuf>,
+    /// Files of the matched folder matched with no file of this folder.
+    #[serde(rename = "matchedFolderUnmatchedFiles")]
+    pub matched_folder_unmatched_files: Vec<PathBuf>,
+    /// Promotion the matched folder was archived from, when it is not part of the checked
+    /// promotion.
+    #[serde(rename = "archivedPromotionId")]
+    pub archived_promotion_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
@@ -66,7 +223,7 @@ pub struct ApiFolderResultReport {
     pub matches: Vec<ApiMatchDetail>,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
 pub struct ComprehensivePlagiarismResponse {
     #[serde(rename = "projectId")]
     pub project_id: String,
@@ -74,6 +231,179 @@ pub struct ComprehensivePlagiarismResponse {
     pub promotion_id: String,
     #[serde(rename = "folderResults")]
     pub folder_results: Vec<ApiFolderResultReport>,
+    pub config: ComparisonConfig,
+    /// Whether the fingerprints of a registered starter-code archive were left out of
+    /// every score.
+    #[serde(rename = "templateExcluded")]
+    pub template_excluded: bool,
+    /// Fingerprints ignored because they are shared by more than `maxFingerprintFrequency`
+    /// of the submissions.
+    #[serde(rename = "filteredFingerprints")]
+    pub filtered_fingerprints: ApiFingerprintCounts,
+    /// Groups of submissions linked by whole-project combined scores of at least
+    /// `clusterThreshold`, largest first.
+    pub clusters: Vec<ApiCluster>,
+    /// Submissions left out of the check, with the stage at which they failed.
+    #[serde(rename = "skippedSubmissions")]
+    pub skipped_submissions: Vec<ApiSkippedSubmission>,
+}
+
+/// Stage of a check at which a submission failed.
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipStage {
+    /// The zip could not be downloaded from the store.
+    Download,
+    /// The downloaded file is not a readable zip archive.
+    OpenArchive,
+    /// The zip archive could not be extracted.
+    Extraction,
+    /// The extracted folder could not be normalized for comparison.
+    Processing,
+}
+
+/// Submission left out of a check.
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Debug, PartialEq)]
+pub struct ApiSkippedSubmission {
+    pub key: String,
+    #[serde(rename = "submissionId")]
+    pub submission_id: String,
+    pub stage: SkipStage,
+    pub error: String,
+    /// Download attempts made, more than one when transient errors were retried.
+    pub attempts: u32,
+}
+
+/// Submissions linked to each other, directly or through other members, such as a ring
+/// of students sharing one solution.
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Debug, PartialEq)]
+pub struct ApiCluster {
+    /// Folder names of the members, archived submissions included, in alphabetical order.
+    pub members: Vec<String>,
+    /// Mean combined score over every pair of members, pairs that share nothing counting
+    /// as 0.
+    #[serde(rename = "averageScore")]
+    pub average_score: f64,
+    /// Lowest combined score over every pair of members.
+    #[serde(rename = "minScore")]
+    pub min_score: f64,
+    /// Member uploaded first, which the others most likely copied from. Absent when no
+    /// upload time can be read from the folder names.
+    #[serde(rename = "likelyOrigin")]
+    pub likely_origin: Option<String>,
+}
+
+/// Number of distinct fingerprints per algorithm.
+#[derive(
+    Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Copy, Debug, Default, PartialEq,
+)]
+pub struct ApiFingerprintCounts {
+    pub moss: usize,
+    #[serde(rename = "normalizedMoss")]
+    pub normalized_moss: usize,
+    pub gst: usize,
+    #[serde(rename = "rabinKarp")]
+    pub rabin_karp: usize,
+}
+
+impl From<&ExcludedFingerprints> for ApiFingerprintCounts {
+    fn from(fingerprints: &ExcludedFingerprints) -> Self {
+        Self {
+            moss: fingerprints.moss.len(),
+            normalized_moss: fingerprints.normalized_moss.len(),
+            gst: fingerprints.gst.len(),
+            rabin_karp: fingerprints.rabin_karp.len(),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct JobPath {
+    #[serde(rename = "jobId")]
+    pub job_id: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct SubmissionPairPath {
+    #[serde(rename = "jobId")]
+    pub job_id: String,
+    #[serde(rename = "submissionA")]
+    pub submission_a: String,
+    #[serde(rename = "submissionB")]
+    pub submission_b: String,
+}
+
+#[derive(Deserialize, JsonSchema, ApiComponent)]
+pub struct TemplatePath {
+    #[serde(rename = "projectId")]
+    pub project_id: String,
+    #[serde(rename = "promotionId")]
+    pub promotion_id: String,
+    pub step: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct TemplateRegisteredResponse {
+    pub key: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: usize,
+}
+
+/// Raw zip archive sent as the request body, up to [`MAX_TEMPLATE_ARCHIVE_BYTES`].
+pub struct ZipArchiveBody(pub Bytes);
+
+impl FromRequest for ZipArchiveBody {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let payload = web::Payload::from_request(req, payload);
+        Box::pin(async move {
+            let bytes = payload
+                .await?
+                .to_bytes_limited(MAX_TEMPLATE_ARCHIVE_BYTES)
+                .await
+                .map_err(|_| {
+                    actix_web::error::ErrorPayloadTooLarge(format!(
+                        "Archive exceeds {MAX_TEMPLATE_ARCHIVE_BYTES} bytes"
+                    ))
+                })??;
+            Ok(ZipArchiveBody(bytes))
+        })
+    }
+}
+
+impl ApiComponent for ZipArchiveBody {
+    fn content_type() -> String {
+        "application/zip".to_string()
+    }
+
+    fn child_schemas() -> Vec<(String, ReferenceOr<Schema>)> {
+        vec![]
+    }
+
+    fn schema() -> Option<(String, ReferenceOr<Schema>)> {
+        None
+    }
+
+    fn request_body() -> Option<RequestBody> {
+        let binary = Schema::Object(SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            format: Some("binary".to_string()),
+            ..Default::default()
+        });
+        Some(RequestBody {
+            content: BTreeMap::from_iter([(
+                Self::content_type(),
+                MediaType {
+                    schema: Some(binary.into()),
+                    ..Default::default()
+                },
+            )]),
+            required: Some(true),
+            ..Default::default()
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
@@ -95,16 +425,11 @@ pub struct TempPlagiarismCheckResponse {
     pub processed_project_ids: Vec<String>,
 }
 
-fn calculate_combined_score(moss_score: f64, rabin_karp_score: f64) -> f64 {
-    // Weight the scores - adjust weights as needed
-    const MOSS_WEIGHT: f64 = 0.6;
-    const RABIN_KARP_WEIGHT: f64 = 0.4;
-
-    (moss_score * MOSS_WEIGHT + rabin_karp_score * RABIN_KARP_WEIGHT)
-        / (MOSS_WEIGHT + RABIN_KARP_WEIGHT)
-}
-
-fn generate_file_flags(moss_score: f64, rabin_karp_score: f64) -> Vec<String> {
+fn generate_file_flags(
+    moss_score: f64,
+    rabin_karp_score: f64,
+    max_containment: f64,
+) -> Vec<String> {
     let mut flags = Vec::new();
 
     // High similarity flags
@@ -125,10 +450,19 @@ fn generate_file_flags(moss_score: f64, rabin_karp_score: f64) -> Vec<String> {
         flags.push("HIGH_RABIN_KARP_MATCH".to_string());
     }
 
+    // One side is mostly contained in the other, e.g. a file copied into a larger one
+    if max_containment > 80.0 {
+        flags.push("HIGH_CONTAINMENT".to_string());
+    }
+
     flags
 }
 
-fn generate_overall_flags(moss_score: f64, rabin_karp_score: f64) -> Vec<String> {
+fn generate_overall_flags(
+    moss_score: f64,
+    rabin_karp_score: f64,
+    max_containment: f64,
+) -> Vec<String> {
     let mut flags = Vec::new();
 
     // High overall similarity
@@ -146,10 +480,67 @@ fn generate_overall_flags(moss_score: f64, rabin_karp_score: f64) -> Vec<String>
         flags.push("SIGNIFICANT_RABIN_KARP_MATCH".to_string());
     }
 
+    // One project is mostly contained in the other
+    if max_containment > 80.0 {
+        flags.push("HIGH_CONTAINMENT".to_string());
+    }
+
     flags
 }
 
-#[api_operation(summary = "Downloads, processes, and compares project submissions for plagiarism.")]
+fn empty_response(body: &BodyRequest) -> ComprehensivePlagiarismResponse {
+    ComprehensivePlagiarismResponse {
+        project_id: body.project_id.clone(),
+        promotion_id: body.promotion_id.clone(),
+        folder_results: vec![],
+        config: body.config.clone(),
+        template_excluded: false,
+        filtered_fingerprints: ApiFingerprintCounts::default(),
+        clusters: vec![],
+        skipped_submissions: vec![],
+    }
+}
+
+/// Object storage prefix holding the submissions of a project step.
+fn submissions_prefix(project_id: &str, promotion_id: &str, step: &str) -> String {
+    format!("project-{project_id}/promo-{promotion_id}/step-{step}/")
+}
+
+/// Upload time of a submission, in milliseconds since the epoch, read from its
+/// `<groupId>-<timestamp>` identifier.
+fn upload_timestamp(submission_id: &str) -> Option<u64> {
+    submission_id.rsplit_once('-')?.1.parse().ok()
+}
+
+/// Object storage key of the starter-code archive of a project step.
+pub fn template_key(project_id: &str, promotion_id: &str, step: &str) -> String {
+    format!(
+        "{}{TEMPLATE_ARCHIVE_NAME}",
+        submissions_prefix(project_id, promotion_id, step)
+    )
+}
+
+/// Every endpoint of the service, below `/plagiarism`.
+pub fn routes() -> Scope {
+    scope("/plagiarism")
+        .service(
+            scope("/checks")
+                .service(resource("").route(post().to(checks_projects)))
+                .service(resource("/{jobId}").route(get().to(get_check_status)))
+                .service(resource("/{jobId}/events").route(get().to(stream_check_events)))
+                .service(
+                    resource("/{jobId}/report/{submissionA}/{submissionB}")
+                        .route(get().to(comparison_report)),
+                ),
+        )
+        .service(scope("/templates").service(
+            resource("/{projectId}/{promotionId}/{step}").route(put().to(register_template)),
+        ))
+}
+
+#[api_operation(
+    summary = "Queues a plagiarism check of the project submissions and returns its job id."
+)]
 pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>) -> impl Responder {
     if body.project_id.trim().is_empty() {
         return HttpResponse::BadRequest().json(serde_json::json!({
@@ -169,26 +560,239 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
         }));
     }
 
-    let s3_directory_prefix = format!(
-        "project-{}/promo-{}/step-{}/",
-        body.project_id, body.promotion_id, body.step
+    if let Err(e) = body.config.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid comparison config: {e}")
+        }));
+    }
+
+    // Archived promotion ids end up in storage prefixes and extraction paths.
+    if body.archived_promotion_ids.iter().any(|id| {
+        id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) || *id == body.promotion_id
+    }) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "archivedPromotionIds must only hold other, alphanumeric promotion ids"
+        }));
+    }
+
+    let job_id = app_state.jobs.submit(body.into_inner());
+    println!("Queued plagiarism check job {job_id}");
+
+    HttpResponse::Accepted().json(JobAcceptedResponse {
+        job_id,
+        status: JobStatus::Queued,
+    })
+}
+
+#[api_operation(summary = "Returns the status of a plagiarism check job and its report once done.")]
+pub async fn get_check_status(
+    path: PathParam<JobPath>,
+    app_state: Data<AppState>,
+) -> impl Responder {
+    match app_state.jobs.status(&path.job_id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown plagiarism check job: {}", path.job_id)
+        })),
+    }
+}
+
+#[api_operation(summary = "Streams the progress of a plagiarism check job as Server-Sent Events.")]
+pub async fn stream_check_events(
+    path: PathParam<JobPath>,
+    app_state: Data<AppState>,
+) -> impl Responder {
+    let Some((history, receiver)) = app_state.jobs.subscribe(&path.job_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Unknown plagiarism check job: {}", path.job_id)
+        }));
+    };
+
+    // Replay what already happened, then follow the live events until the job ends.
+    let finished = history.iter().any(ProgressEvent::is_terminal);
+    let replay = stream::iter(history.into_iter().map(Ok));
+    let live = stream::unfold(
+        (receiver, finished),
+        |(mut receiver, finished)| async move {
+            if finished {
+                return None;
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let finished = event.is_terminal();
+                        return Some((Ok(event), (receiver, finished)));
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("Progress stream lagged behind, skipped {skipped} events");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
     );
-    let base_extract_target_dir = &app_state.extract_base_path;
-    let s3_file_keys = match s3::list_files_in_directory(&s3_directory_prefix).await {
-        Ok(keys) => keys,
+
+    let body = replay
+        .chain(live)
+        .map(|event: Result<ProgressEvent, actix_web::Error>| {
+            event.map(|event| Bytes::from(event.to_sse_frame()))
+        });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(body)
+}
+
+#[api_operation(
+    summary = "Renders a side-by-side HTML comparison of two submissions of a finished check."
+)]
+pub async fn comparison_report(
+    path: PathParam<SubmissionPairPath>,
+    app_state: Data<AppState>,
+) -> impl Responder {
+    let SubmissionPair {
+        project_a,
+        project_b,
+        config,
+        excluded,
+    } = match app_state
+        .jobs
+        .submission_pair(&path.job_id, &path.submission_a, &path.submission_b)
+    {
+        Ok(pair) => pair,
         Err(e) => {
-            eprintln!("Error listing S3 files for prefix '{s3_directory_prefix}': {e}");
-            return HttpResponse::InternalServerError().json(ComprehensivePlagiarismResponse {
-                project_id: body.project_id.clone(),
-                promotion_id: body.promotion_id.clone(),
-                folder_results: vec![],
-            });
+            let body = serde_json::json!({ "error": e.to_string() });
+            return match e {
+                SubmissionLookupError::NotDone(_) => HttpResponse::Conflict().json(body),
+                _ => HttpResponse::NotFound().json(body),
+            };
         }
     };
 
-    let zip_file_keys: Vec<&String> = s3_file_keys
+    let rendered = web::block(move || {
+        let report = compare_normalized_projects(&project_a, &project_b, &config, &excluded);
+        render_comparison_html(&project_a, &project_b, &report)
+    })
+    .await;
+
+    match rendered {
+        Ok(html) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(html),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Failed to render comparison report: {e}")
+        })),
+    }
+}
+
+#[api_operation(
+    summary = "Registers the starter-code zip of a project step, excluded from future checks."
+)]
+pub async fn register_template(
+    path: PathParam<TemplatePath>,
+    archive: ZipArchiveBody,
+    app_state: Data<AppState>,
+) -> impl Responder {
+    let ZipArchiveBody(archive) = archive;
+    if let Err(e) = zip::ZipArchive::new(std::io::Cursor::new(&archive[..])) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Starter code must be a zip archive: {e}")
+        }));
+    }
+
+    let key = template_key(&path.project_id, &path.promotion_id, &path.step);
+    let size_bytes = archive.len();
+    if let Err(e) = app_state.store.put_object(&key, archive.to_vec()).await {
+        eprintln!("Failed to store starter code {key}: {e}");
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": format!("Failed to store starter code: {e}")
+        }));
+    }
+    println!("Registered starter code {key} ({size_bytes} bytes)");
+
+    HttpResponse::Created().json(TemplateRegisteredResponse { key, size_bytes })
+}
+
+/// Extracted submissions of a check, along with the extracted starter code of the step
+/// when one was registered.
+pub struct DownloadedSubmissions {
+    /// Folder and identifier of every extracted submission, archived ones included.
+    pub submissions: Vec<(PathBuf, String)>,
+    /// Promotion of each archived submission, by submission identifier.
+    pub archived_promotions: HashMap<String, String>,
+    pub template_dir: Option<PathBuf>,
+    /// Storage key and download attempts of each extracted submission, by submission
+    /// identifier.
+    pub sources: HashMap<String, (String, u32)>,
+    /// Submissions that could not be downloaded or extracted.
+    pub skipped: Vec<ApiSkippedSubmission>,
+}
+
+/// Reads the zip archive `zip_data`.
+fn open_zip(zip_data: Vec<u8>) -> Result<ZipArchive<Cursor<Vec<u8>>>, String> {
+    ZipArchive::new(Cursor::new(zip_data)).map_err(|e| format!("Failed to read zip archive: {e}"))
+}
+
+/// Unpacks the untrusted `archive` into `extract_to_path` within `limits`, replacing
+/// whatever was there.
+fn extract_zip(
+    mut archive: ZipArchive<Cursor<Vec<u8>>>,
+    extract_to_path: &Path,
+    limits: &ExtractionLimits,
+) -> Result<(), String> {
+    // Cleanup existing directory if it exists
+    if extract_to_path.exists() {
+        if let Err(e) = std::fs::remove_dir_all(extract_to_path) {
+            eprintln!(
+                "Warning: Failed to remove existing directory {}: {}",
+                extract_to_path.display(),
+                e
+            );
+        }
+    }
+    std::fs::create_dir_all(extract_to_path).map_err(|e| {
+        format!(
+            "Failed to create extraction directory {}: {}",
+            extract_to_path.display(),
+            e
+        )
+    })?;
+
+    extract_archive(&mut archive, extract_to_path, limits).map_err(|e| e.to_string())
+}
+
+/// Identifier of a submission stored under `s3_zip_key`, prefixed with its promotion when
+/// it is archived so it cannot collide with a submission of the checked promotion.
+fn submission_id(s3_zip_key: &str, archived_promotion_id: Option<&str>) -> String {
+    let zip_file_name_on_s3 = std::path::Path::new(s3_zip_key)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("unknown_s3_zip_name.zip");
+    let submission_id = zip_file_name_on_s3.replace(".zip", "");
+    match archived_promotion_id {
+        Some(promotion_id) => format!("promo-{promotion_id}-{submission_id}"),
+        None => submission_id,
+    }
+}
+
+/// Keys of the zip submissions of a promotion, leaving its starter code out.
+async fn list_submission_keys(
+    store: &dyn SubmissionStore,
+    body: &BodyRequest,
+    promotion_id: &str,
+) -> Result<Vec<String>, String> {
+    let s3_directory_prefix = submissions_prefix(&body.project_id, promotion_id, &body.step);
+    let s3_template_key = template_key(&body.project_id, promotion_id, &body.step);
+    let s3_file_keys = store
+        .list_prefix(&s3_directory_prefix)
+        .await
+        .map_err(|e| format!("Error listing S3 files for prefix '{s3_directory_prefix}': {e}"))?;
+
+    let zip_file_keys: Vec<String> = s3_file_keys
         .iter()
-        .filter(|k| k.ends_with(".zip"))
+        .filter(|k| k.ends_with(".zip") && **k != s3_template_key)
+        .cloned()
         .collect();
     println!(
         "Found {} S3 files, {} are zips under prefix '{}'.",
@@ -196,137 +800,356 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
         zip_file_keys.len(),
         s3_directory_prefix
     );
+    Ok(zip_file_keys)
+}
 
-    if zip_file_keys.is_empty() {
-        println!("No zip files found in S3 directory for processing.");
-        return HttpResponse::Ok().json(ComprehensivePlagiarismResponse {
-            project_id: body.project_id.clone(),
-            promotion_id: body.promotion_id.clone(),
-            folder_results: vec![],
-        });
+/// Downloads every zip submission under the request's S3 prefix and those of the
+/// archived promotions, and extracts each one into its own folder below `extract_dir`,
+/// along with the step's starter code if one was registered. Up to
+/// `max_concurrent_downloads` submissions are downloaded and extracted at once, each
+/// within `limits`. Those that fail to download or extract are skipped and recorded,
+/// while a starter code that cannot be used fails the whole download.
+pub async fn download_submissions(
+    store: &dyn SubmissionStore,
+    body: &BodyRequest,
+    extract_dir: &Path,
+    max_concurrent_downloads: usize,
+    limits: &ExtractionLimits,
+    progress: &(dyn Fn(ProgressEvent) + Sync),
+) -> Result<DownloadedSubmissions, String> {
+    let s3_template_key = template_key(&body.project_id, &body.promotion_id, &body.step);
+    let mut zip_file_keys: Vec<(String, Option<&str>)> =
+        list_submission_keys(store, body, &body.promotion_id)
+            .await?
+            .into_iter()
+            .map(|key| (key, None))
+            .collect();
+    for archived_promotion_id in &body.archived_promotion_ids {
+        let archived_keys = list_submission_keys(store, body, archived_promotion_id).await?;
+        zip_file_keys.extend(
+            archived_keys
+                .into_iter()
+                .map(|key| (key, Some(archived_promotion_id.as_str()))),
+        );
     }
 
-    let mut extracted_submission_details: Vec<(PathBuf, String)> = Vec::new();
-
-    for s3_zip_key in zip_file_keys {
-        let zip_file_name_on_s3 = std::path::Path::new(s3_zip_key)
-            .file_name()
-            .and_then(|name| name.to_str())
-            .unwrap_or("unknown_s3_zip_name.zip");
-
-        let submission_id = zip_file_name_on_s3.replace(".zip", "");
-        let extract_to_path = base_extract_target_dir.join(&submission_id);
-
+    let template = store
+        .head_object(&s3_template_key)
+        .await
+        .map_err(|e| format!("Failed to look up starter code {s3_template_key}: {e}"))?;
+    let template_dir = if template.is_some() {
+        let template_dir = extract_dir.join(TEMPLATE_ARCHIVE_NAME.replace(".zip", ""));
+        let (zip_data, _) = get_object_with_retries(store, &s3_template_key).await;
+        let zip_data = zip_data
+            .map_err(|e| format!("Failed to download starter code {s3_template_key}: {e}"))?;
+        let path = template_dir.clone();
+        let limits = *limits;
+        web::block(move || {
+            open_zip(zip_data).and_then(|archive| extract_zip(archive, &path, &limits))
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Extraction was interrupted: {e}")))
+        .map_err(|e| format!("Unusable starter code {s3_template_key}: {e}"))?;
         println!(
-            "Processing S3 zip: {} to {}",
-            s3_zip_key,
-            extract_to_path.display()
+            "Extracted starter code {} to {}",
+            s3_template_key,
+            template_dir.display()
         );
+        Some(template_dir)
+    } else {
+        None
+    };
 
-        // Cleanup existing directory if it exists
-        if extract_to_path.exists() {
-            if let Err(e) = std::fs::remove_dir_all(&extract_to_path) {
-                eprintln!(
-                    "Warning: Failed to remove existing directory {}: {}",
-                    extract_to_path.display(),
-                    e
+    let total_zips = zip_file_keys.len();
+    let downloaded_zips = AtomicUsize::new(0);
+    let downloads = stream::iter(zip_file_keys).map(|(s3_zip_key, archived_promotion_id)| {
+        let downloaded_zips = &downloaded_zips;
+        async move {
+            let submission_id = submission_id(&s3_zip_key, archived_promotion_id);
+            let on_downloaded = || {
+                progress(ProgressEvent::ZipDownloaded {
+                    key: s3_zip_key.clone(),
+                    submission_id: submission_id.clone(),
+                    downloaded: downloaded_zips.fetch_add(1, Ordering::Relaxed) + 1,
+                    total: total_zips,
+                });
+            };
+            let outcome = download_submission(
+                store,
+                &s3_zip_key,
+                &submission_id,
+                extract_dir,
+                limits,
+                on_downloaded,
+            )
+            .await;
+            (submission_id, archived_promotion_id, outcome)
+        }
+    });
+    // Results are buffered in order, so the submissions do not depend on which download
+    // finishes first.
+    let outcomes: Vec<(String, Option<&str>, Result<_, ApiSkippedSubmission>)> = downloads
+        .buffered(max_concurrent_downloads.max(1))
+        .collect()
+        .await;
+
+    let mut extracted_submission_details: Vec<(PathBuf, String)> = Vec::new();
+    let mut archived_promotions: HashMap<String, String> = HashMap::new();
+    let mut sources: HashMap<String, (String, u32)> = HashMap::new();
+    let mut skipped: Vec<ApiSkippedSubmission> = Vec::new();
+    for (submission_id, archived_promotion_id, outcome) in outcomes {
+        match outcome {
+            Ok((extract_to_path, source)) => {
+                println!(
+                    "Successfully extracted {} to {}",
+                    submission_id,
+                    extract_to_path.display()
                 );
+                if let Some(promotion_id) = archived_promotion_id {
+                    archived_promotions.insert(submission_id.clone(), promotion_id.to_string());
+                }
+                sources.insert(submission_id.clone(), source);
+                extracted_submission_details.push((extract_to_path, submission_id));
             }
+            Err(skipped_submission) => skipped.push(skipped_submission),
         }
-        if let Err(e) = std::fs::create_dir_all(&extract_to_path) {
-            eprintln!(
-                "Failed to create extraction directory {}: {}. Skipping this zip.",
-                extract_to_path.display(),
-                e
-            );
-            continue;
+    }
+
+    Ok(DownloadedSubmissions {
+        submissions: extracted_submission_details,
+        archived_promotions,
+        template_dir,
+        sources,
+        skipped,
+    })
+}
+
+/// Downloads the zip submission stored under `s3_zip_key` and extracts it into its own
+/// folder below `extract_dir` within `limits`, calling `on_downloaded` in between.
+/// Returns the folder along with the key and the download attempts made.
+async fn download_submission(
+    store: &dyn SubmissionStore,
+    s3_zip_key: &str,
+    submission_id: &str,
+    extract_dir: &Path,
+    limits: &ExtractionLimits,
+    on_downloaded: impl FnOnce(),
+) -> Result<(PathBuf, (String, u32)), ApiSkippedSubmission> {
+    let extract_to_path = extract_dir.join(submission_id);
+    println!(
+        "Processing S3 zip: {} to {}",
+        s3_zip_key,
+        extract_to_path.display()
+    );
+
+    let (zip_data, attempts) = get_object_with_retries(store, s3_zip_key).await;
+    let skip = |stage: SkipStage, error: String| {
+        eprintln!("{s3_zip_key}: {error}. Skipping this zip.");
+        ApiSkippedSubmission {
+            key: s3_zip_key.to_string(),
+            submission_id: submission_id.to_string(),
+            stage,
+            error,
+            attempts,
         }
+    };
+    let zip_data = zip_data.map_err(|e| {
+        skip(
+            SkipStage::Download,
+            format!("Failed to download after {attempts} attempts: {e}"),
+        )
+    })?;
+    on_downloaded();
+    let archive = open_zip(zip_data).map_err(|e| skip(SkipStage::OpenArchive, e))?;
 
-        match s3::get_file_from_s3(s3_zip_key).await {
-            Ok(zip_data) => {
-                let reader = std::io::Cursor::new(zip_data);
-                match zip::ZipArchive::new(reader) {
-                    Ok(mut archive) => {
-                        if let Err(e) = archive.extract(&extract_to_path) {
-                            eprintln!(
-                                "Failed to extract zip archive {} to {}: {}",
-                                s3_zip_key,
-                                extract_to_path.display(),
-                                e
-                            );
-                            continue;
-                        }
-                        println!(
-                            "Successfully extracted {} to {}",
-                            s3_zip_key,
-                            extract_to_path.display()
-                        );
-                        extracted_submission_details.push((extract_to_path, submission_id));
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to read zip archive from S3 key {s3_zip_key}: {e}");
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to download S3 key {s3_zip_key}: {e}");
+    let path = extract_to_path.clone();
+    let limits = *limits;
+    web::block(move || extract_zip(archive, &path, &limits))
+        .await
+        .unwrap_or_else(|e| Err(format!("Extraction was interrupted: {e}")))
+        .map_err(|e| skip(SkipStage::Extraction, e))?;
+    Ok((extract_to_path, (s3_zip_key.to_string(), attempts)))
+}
+
+/// Downloads the object stored under `key`, retrying transient failures with exponential
+/// backoff. Returns the content or the last error, along with the attempts made.
+async fn get_object_with_retries(
+    store: &dyn SubmissionStore,
+    key: &str,
+) -> (Result<Vec<u8>, StoreError>, u32) {
+    let mut backoff = INITIAL_DOWNLOAD_BACKOFF;
+    let mut attempts = 1;
+    loop {
+        match store.get_object(key).await {
+            Err(e) if e.is_transient() && attempts < MAX_DOWNLOAD_ATTEMPTS => {
+                eprintln!(
+                    "Attempt {attempts} to download {key} failed: {e}. Retrying in {backoff:?}."
+                );
+                actix_web::rt::time::sleep(backoff).await;
+                backoff *= 2;
+                attempts += 1;
             }
+            result => return (result, attempts),
         }
     }
+}
+
+/// Outcome of [`analyze_submissions`]: the API report along with the normalized
+/// submissions it was computed from, kept to render pairwise reports on demand.
+pub struct SubmissionAnalysis {
+    pub response: ComprehensivePlagiarismResponse,
+    pub projects: Vec<NormalizedProject>,
+    pub excluded: ExcludedFingerprints,
+}
+
+/// Fingerprints of the extracted starter code in `template_dir`, or none when the step
+/// has no starter code. Starter code that cannot be processed is an error, as comparing
+/// without it would report the code every student was given as plagiarism.
+fn template_fingerprints(
+    template_dir: Option<&Path>,
+    config: &ComparisonConfig,
+) -> Result<ExcludedFingerprints, String> {
+    let Some(template_dir) = template_dir else {
+        return Ok(ExcludedFingerprints::default());
+    };
+    let template = process_project_folder(template_dir, "base-code")
+        .map_err(|e| format!("Unusable starter code: {e}"))?;
+    println!(
+        "Excluding {} starter code files from the comparison.",
+        template.files.len()
+    );
+    Ok(ExcludedFingerprints::from_project(&template, config))
+}
+
+/// Normalizes the extracted submissions, compares every pair of them without the
+/// starter code fingerprints and aggregates the pairwise reports into the API response.
+/// Archived submissions are only compared with those of the checked promotion, and get no
+/// folder result of their own.
+/// Fails when the starter code cannot be processed.
+/// This is CPU bound and must not run on an actix worker thread; pairs are compared in
+/// parallel on the current rayon thread pool.
+pub fn analyze_submissions(
+    body: &BodyRequest,
+    downloaded: &DownloadedSubmissions,
+    progress: &(dyn Fn(ProgressEvent) + Sync),
+) -> Result<SubmissionAnalysis, String> {
+    let extracted_submission_details = &downloaded.submissions;
+    let mut excluded = template_fingerprints(downloaded.template_dir.as_deref(), &body.config)?;
+    let template_excluded = !excluded.is_empty();
 
     if extracted_submission_details.is_empty() {
         println!("No submissions were successfully downloaded and extracted for analysis.");
-        return HttpResponse::Ok().json(ComprehensivePlagiarismResponse {
-            project_id: body.project_id.clone(),
-            promotion_id: body.promotion_id.clone(),
-            folder_results: vec![],
+        return Ok(SubmissionAnalysis {
+            response: ComprehensivePlagiarismResponse {
+                template_excluded,
+                skipped_submissions: downloaded.skipped.clone(),
+                ..empty_response(body)
+            },
+            projects: Vec::new(),
+            excluded,
         });
     }
 
     // --- 2. Process Each Extracted Project Folder ---
     let mut normalized_projects: Vec<NormalizedProject> = Vec::new();
-    for (folder_path, submission_id) in &extracted_submission_details {
+    let mut skipped_submissions = downloaded.skipped.clone();
+    for (folder_path, submission_id) in extracted_submission_details {
         match process_project_folder(folder_path, submission_id) {
             Ok(norm_proj) => {
+                progress(ProgressEvent::ProjectNormalized {
+                    submission_id: submission_id.clone(),
+                    file_count: norm_proj.files.len(),
+                });
                 normalized_projects.push(norm_proj);
             }
             Err(e) => {
                 eprintln!(
-                    "Error processing project folder '{}': {:?}. Skipping.",
+                    "Error processing project folder '{}': {}. Skipping.",
                     folder_path.display(),
                     e
                 );
+                let (key, attempts) = downloaded
+                    .sources
+                    .get(submission_id)
+                    .cloned()
+                    .unwrap_or_default();
+                skipped_submissions.push(ApiSkippedSubmission {
+                    key,
+                    submission_id: submission_id.clone(),
+                    stage: SkipStage::Processing,
+                    error: e.to_string(),
+                    attempts,
+                });
             }
         }
     }
 
-    if normalized_projects.len() < 2 {
+    let is_archived =
+        |np: &NormalizedProject| downloaded.archived_promotions.contains_key(&np.project_id);
+    let pairs: Vec<(usize, usize)> = (0..normalized_projects.len())
+        .flat_map(|i| ((i + 1)..normalized_projects.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| {
+            !(is_archived(&normalized_projects[i]) && is_archived(&normalized_projects[j]))
+        })
+        .collect();
+
+    if pairs.is_empty() {
         println!(
             "Not enough projects successfully processed for comparison (need at least 2, got {}).",
             normalized_projects.len()
         );
         let mut folder_results_for_single: Vec<ApiFolderResultReport> = Vec::new();
-        for np in normalized_projects {
+        for np in normalized_projects.iter().filter(|np| !is_archived(np)) {
             folder_results_for_single.push(ApiFolderResultReport {
-                folder_name: np.project_id,
-                sha1: np.concatenated_source_hash,
+                folder_name: np.project_id.clone(),
+                sha1: np.concatenated_source_hash.clone(),
                 plagiarism_percentage: 0.0,
                 matches: vec![],
             });
         }
-        return HttpResponse::Ok().json(ComprehensivePlagiarismResponse {
-            project_id: body.project_id.clone(),
-            promotion_id: body.promotion_id.clone(),
-            folder_results: folder_results_for_single,
+        return Ok(SubmissionAnalysis {
+            response: ComprehensivePlagiarismResponse {
+                folder_results: folder_results_for_single,
+                template_excluded,
+                skipped_submissions,
+                ..empty_response(body)
+            },
+            projects: normalized_projects,
+            excluded,
         });
     }
 
+    let common = ExcludedFingerprints::common_to(&normalized_projects, &body.config);
+    let filtered_fingerprints = ApiFingerprintCounts::from(&common);
+    if !common.is_empty() {
+        println!(
+            "Ignoring {} MOSS and {} Rabin-Karp fingerprints shared by more than {:.0}% of the submissions.",
+            filtered_fingerprints.moss,
+            filtered_fingerprints.rabin_karp,
+            body.config.max_fingerprint_frequency * 100.0
+        );
+    }
+    excluded.extend(common);
+
     // --- 3. Pairwise Project Comparisons ---
-    let mut comparison_reports: Vec<ProjectComparisonReport> = Vec::new();
-    for i in 0..normalized_projects.len() {
-        for j in (i + 1)..normalized_projects.len() {
+    let total_pairs = pairs.len();
+    let candidate_pairs =
+        FingerprintIndex::build(&normalized_projects, &body.config, &excluded).candidate_pairs();
+    println!(
+        "{} of {} project pairs share fingerprints and are compared.",
+        candidate_pairs.len(),
+        total_pairs
+    );
+    let no_candidates = CandidatePairs::default();
+    let compared_pairs = Mutex::new(0);
+    // Pairs are compared in parallel but collected in order, so the report does not
+    // depend on the number of threads.
+    let comparison_reports: Vec<ProjectComparisonReport> = pairs
+        .into_par_iter()
+        .map(|(i, j)| {
             let proj_a = &normalized_projects[i];
             let proj_b = &normalized_projects[j];
+            let candidates = candidate_pairs.get(&(i, j)).unwrap_or(&no_candidates);
 
             let total_files = proj_a.files.len() + proj_b.files.len();
             println!(
@@ -336,25 +1159,75 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
                 proj_b.files.len()
             );
 
-            let comparison_results = compare_normalized_projects(proj_a, proj_b);
+            let comparison_results = compare_normalized_projects_indexed(
+                proj_a,
+                proj_b,
+                &body.config,
+                &excluded,
+                candidates,
+            );
             println!(
                 "  -> Found {} file-to-file comparison results for {} total files in project A and {} total files in project B",
                 comparison_results.file_to_file_comparisons.len(),
                 proj_a.files.len(),
                 proj_b.files.len()
             );
-            comparison_reports.push(comparison_results);
-        }
-    }
+
+            // Counting and reporting under the same lock keeps the events in order.
+            let mut compared = compared_pairs.lock().unwrap_or_else(PoisonError::into_inner);
+            *compared += 1;
+            progress(ProgressEvent::PairCompared {
+                project_a: proj_a.project_id.clone(),
+                project_b: proj_b.project_id.clone(),
+                compared: *compared,
+                total: total_pairs,
+            });
+            drop(compared);
+
+            comparison_results
+        })
+        .collect();
     println!(
         "Completed {} pairwise project comparisons.",
         comparison_reports.len()
     );
 
     // --- 4. Aggregate and Format Final API Response ---
+    Ok(SubmissionAnalysis {
+        response: ComprehensivePlagiarismResponse {
+            template_excluded,
+            filtered_fingerprints,
+            skipped_submissions,
+            ..build_response(
+                body,
+                &normalized_projects,
+                &downloaded.archived_promotions,
+                comparison_reports,
+            )
+        },
+        projects: normalized_projects,
+        excluded,
+    })
+}
+
+fn build_response(
+    body: &BodyRequest,
+    normalized_projects: &[NormalizedProject],
+    archived_promotions: &HashMap<String, String>,
+    comparison_reports: Vec<ProjectComparisonReport>,
+) -> ComprehensivePlagiarismResponse {
     let mut final_folder_results_map: HashMap<String, ApiFolderResultReport> = HashMap::new();
+    let project_positions: HashMap<&str, usize> = normalized_projects
+        .iter()
+        .enumerate()
+        .map(|(position, np)| (np.project_id.as_str(), position))
+        .collect();
+    let mut pair_scores: Vec<(usize, usize, f64)> = Vec::new();
 
-    for np in &normalized_projects {
+    for np in normalized_projects
+        .iter()
+        .filter(|np| !archived_promotions.contains_key(&np.project_id))
+    {
         final_folder_results_map.insert(
             np.project_id.clone(),
             ApiFolderResultReport {
@@ -367,17 +1240,28 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
     }
 
     for report in comparison_reports {
-        let overall_moss_score = report
-            .whole_project_moss_result
-            .as_ref()
-            .map_or(0.0, |r| r.score * 100.0);
+        let overall_moss_score = effective_moss_score(
+            report.whole_project_moss_result.as_ref(),
+            report.whole_project_normalized_moss_result.as_ref(),
+        ) * 100.0;
         let overall_rk_score = report
             .whole_project_rabin_karp_result
             .as_ref()
             .map_or(0.0, |r| r.similarity_score * 100.0);
         let pair_overall_combined_score =
             calculate_combined_score(overall_moss_score, overall_rk_score);
-        let pair_overall_flags = generate_overall_flags(overall_moss_score, overall_rk_score);
+        if let (Some(&a), Some(&b)) = (
+            project_positions.get(report.project1_id.as_str()),
+            project_positions.get(report.project2_id.as_str()),
+        ) {
+            pair_scores.push((a, b, pair_overall_combined_score));
+        }
+        let pair_containment = ApiContainment::new(
+            report.whole_project_moss_result.as_ref(),
+            report.whole_project_rabin_karp_result.as_ref(),
+        );
+        let pair_overall_flags =
+            generate_overall_flags(overall_moss_score, overall_rk_score, pair_containment.max());
 
         // Transform FileComparisonResults to ApiFileComparisonDetail
         let mut api_file_details_for_pair: Vec<ApiFileComparisonDetail> = Vec::new();
@@ -386,12 +1270,24 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
                 .moss_result
                 .as_ref()
                 .map_or(0.0, |r| r.score * 100.0);
+            let normalized_moss_score = enriched_file_comp
+                .normalized_moss_result
+                .as_ref()
+                .map(|r| r.score * 100.0);
+            let ranked_moss_score = moss_score.max(normalized_moss_score.unwrap_or(0.0));
             let rk_score = enriched_file_comp
                 .rabin_karp_result
                 .as_ref()
                 .map_or(0.0, |r| r.similarity_score * 100.0);
-            let combined = calculate_combined_score(moss_score, rk_score);
-            let flags = generate_file_flags(moss_score, rk_score);
+            let combined = calculate_combined_score(ranked_moss_score, rk_score);
+            let containment = ApiContainment::new(
+                enriched_file_comp.moss_result.as_ref(),
+                enriched_file_comp.rabin_karp_result.as_ref(),
+            );
+            let mut flags = generate_file_flags(ranked_moss_score, rk_score, containment.max());
+            if normalized_moss_score.is_some_and(|score| score > 80.0 && moss_score <= 80.0) {
+                flags.push("RENAMED_IDENTIFIERS_SUSPECTED".to_string());
+            }
 
             api_file_details_for_pair.push(ApiFileComparisonDetail {
                 file_name: enriched_file_comp
@@ -404,9 +1300,21 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
                 file_size_bytes: enriched_file_comp.size_bytes_a,
                 lines_of_code: enriched_file_comp.lines_a,
                 moss_score,
+                normalized_moss_score,
                 rabin_karp_score: rk_score,
+                gst_score: enriched_file_comp
+                    .gst_result
+                    .as_ref()
+                    .map(|r| r.similarity * 100.0),
                 combined_score: combined,
+                containment,
                 flags,
+                matched_file_relative_path: enriched_file_comp.file2_path.clone(),
+                matched_regions: enriched_file_comp
+                    .match_regions
+                    .iter()
+                    .map(ApiMatchRegion::from)
+                    .collect(),
             });
         }
 
@@ -416,8 +1324,12 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
                 matched_folder: report.project2_id.clone(),
                 overall_match_percentage: pair_overall_combined_score,
                 combined_score: pair_overall_combined_score,
+                containment: pair_containment,
                 flags: pair_overall_flags.clone(),
                 file_comparisons: api_file_details_for_pair.clone(),
+                unmatched_files: report.unmatched_files_a.clone(),
+                matched_folder_unmatched_files: report.unmatched_files_b.clone(),
+                archived_promotion_id: archived_promotions.get(&report.project2_id).cloned(),
             });
             if pair_overall_combined_score > folder_res_a.plagiarism_percentage {
                 folder_res_a.plagiarism_percentage = pair_overall_combined_score;
@@ -430,8 +1342,12 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
                 matched_folder: report.project1_id.clone(),
                 overall_match_percentage: pair_overall_combined_score,
                 combined_score: pair_overall_combined_score,
+                containment: pair_containment.swapped(),
                 flags: pair_overall_flags,
                 file_comparisons: api_file_details_for_pair,
+                unmatched_files: report.unmatched_files_b.clone(),
+                matched_folder_unmatched_files: report.unmatched_files_a.clone(),
+                archived_promotion_id: archived_promotions.get(&report.project1_id).cloned(),
             });
             if pair_overall_combined_score > folder_res_b.plagiarism_percentage {
                 folder_res_b.plagiarism_percentage = pair_overall_combined_score;
@@ -443,15 +1359,44 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
         final_folder_results_map.into_values().collect();
     analysis_results_vec.sort_by(|a, b| a.folder_name.cmp(&b.folder_name));
 
-    let final_api_response = ComprehensivePlagiarismResponse {
-        project_id: body.project_id.clone(),
-        promotion_id: body.promotion_id.clone(),
+    let clusters = find_clusters(
+        normalized_projects.len(),
+        &pair_scores,
+        body.config.cluster_threshold * 100.0,
+    )
+    .into_iter()
+    .map(|cluster| {
+        let mut members: Vec<String> = cluster
+            .members
+            .iter()
+            .map(|&member| normalized_projects[member].project_id.clone())
+            .collect();
+        members.sort();
+        let likely_origin = members
+            .iter()
+            .filter_map(|member| Some((upload_timestamp(member)?, member)))
+            .min()
+            .map(|(_, member)| member.clone());
+        ApiCluster {
+            members,
+            average_score: cluster.average_score,
+            min_score: cluster.min_score,
+            likely_origin,
+        }
+    })
+    .collect();
+
+    ComprehensivePlagiarismResponse {
         folder_results: analysis_results_vec,
-    };
+        clusters,
+        ..empty_response(body)
+    }
+}
 
-    // --- 5. Cleanup extracted folders ---
+/// Removes the folders produced by [`download_submissions`].
+pub fn cleanup_extracted_folders(extracted_submission_details: &[(PathBuf, String)]) {
     for (path_to_clean, _submission_id) in extracted_submission_details {
-        if let Err(e) = std::fs::remove_dir_all(&path_to_clean) {
+        if let Err(e) = std::fs::remove_dir_all(path_to_clean) {
             eprintln!(
                 "Failed to cleanup extracted folder {}: {}",
                 path_to_clean.display(),
@@ -461,6 +1406,4 @@ pub async fn checks_projects(body: Json<BodyRequest>, app_state: Data<AppState>)
             println!("Cleaned up extracted folder: {}", path_to_clean.display());
         }
     }
-
-    HttpResponse::Ok().json(final_api_response)
 }
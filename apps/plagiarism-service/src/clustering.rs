@@ -0,0 +1,148 @@
+use rustc_hash::FxHashMap;
+
+/// Submissions linked to each other, directly or through other members, by pairwise
+/// scores at or above a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    /// Positions of the member submissions, in increasing order.
+    pub members: Vec<usize>,
+    /// Mean score over every pair of members, counting pairs that were never compared as 0.
+    pub average_score: f64,
+    /// Lowest score over every pair of members.
+    pub min_score: f64,
+}
+
+/// Disjoint-set forest over submission positions.
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut element: usize) -> usize {
+        while self.parents[element] != element {
+            self.parents[element] = self.parents[self.parents[element]];
+            element = self.parents[element];
+        }
+        element
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        // Keeping the lowest position as root makes the result independent of the order
+        // pairs are given in.
+        if root_a != root_b {
+            self.parents[root_a.max(root_b)] = root_a.min(root_b);
+        }
+    }
+}
+
+/// Groups `submission_count` submissions into the connected components of the graph whose
+/// edges are the `pair_scores` at or above `threshold`. `pair_scores` holds the score of
+/// each compared pair of submission positions; submissions linked to no other one are
+/// left out.
+///
+/// Clusters are sorted by decreasing size, then decreasing average score.
+pub fn find_clusters(
+    submission_count: usize,
+    pair_scores: &[(usize, usize, f64)],
+    threshold: f64,
+) -> Vec<Cluster> {
+    let mut components = UnionFind::new(submission_count);
+    let mut scores: FxHashMap<(usize, usize), f64> = FxHashMap::default();
+    for &(a, b, score) in pair_scores {
+        if score >= threshold {
+            components.union(a, b);
+        }
+        scores.insert((a.min(b), a.max(b)), score);
+    }
+
+    let mut members_by_root: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
+    for submission in 0..submission_count {
+        members_by_root
+            .entry(components.find(submission))
+            .or_default()
+            .push(submission);
+    }
+
+    let mut clusters: Vec<Cluster> = members_by_root
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let member_scores: Vec<f64> = members
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &a)| members[i + 1..].iter().map(move |&b| (a, b)))
+                .map(|pair| scores.get(&pair).copied().unwrap_or(0.0))
+                .collect();
+            Cluster {
+                average_score: member_scores.iter().sum::<f64>() / member_scores.len() as f64,
+                min_score: member_scores.iter().copied().fold(f64::INFINITY, f64::min),
+                members,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then(b.average_score.total_cmp(&a.average_score))
+            .then_with(|| a.members.cmp(&b.members))
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_clusters_groups_transitively_linked_submissions() {
+        // 0-1-2 form a chain, 3-4 a pair, and 5 only weakly resembles 0.
+        let pair_scores = [
+            (0, 1, 90.0),
+            (1, 2, 80.0),
+            (0, 2, 40.0),
+            (3, 4, 70.0),
+            (0, 5, 20.0),
+        ];
+        let clusters = find_clusters(6, &pair_scores, 60.0);
+
+        assert_eq!(
+            clusters,
+            vec![
+                Cluster {
+                    members: vec![0, 1, 2],
+                    average_score: 70.0,
+                    min_score: 40.0,
+                },
+                Cluster {
+                    members: vec![3, 4],
+                    average_score: 70.0,
+                    min_score: 70.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_clusters_counts_uncompared_member_pairs_as_zero() {
+        let clusters = find_clusters(3, &[(2, 0, 90.0), (1, 2, 60.0)], 50.0);
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].members, vec![0, 1, 2]);
+        assert_eq!(clusters[0].average_score, 50.0);
+        assert_eq!(clusters[0].min_score, 0.0);
+    }
+
+    #[test]
+    fn test_find_clusters_ignores_pairs_below_threshold() {
+        assert!(find_clusters(2, &[(0, 1, 49.9)], 50.0).is_empty());
+        assert!(find_clusters(0, &[], 50.0).is_empty());
+    }
+}
@@ -1,12 +1,21 @@
 use crate::algorithm::{
-    MossResult as MossComparisonResult, calculate_jaccard_index,
+    DEFAULT_GST_MIN_MATCH_LENGTH, DEFAULT_MOSS_K, DEFAULT_MOSS_WINDOW, GstResult, MatchRegion,
+    MossResult as MossComparisonResult, build_match_regions, calculate_containment,
+    calculate_jaccard_index, compare_documents_gst,
     compare_documents_moss_like as algorithm_compare_documents_moss_like, generate_byte_kgrams,
+    generate_token_kgram_hashes, max_weight_assignment, merge_match_regions,
+    pair_shared_fingerprints,
 };
-use crate::project_processor::NormalizedProject;
-use crate::project_processor::build_blacklist;
-use rustc_hash::FxHashSet;
+use crate::fingerprint_index::CandidatePairs;
+use crate::project_processor::{NormalizedProject, ProcessedFile};
+use crate::project_processor::{SourceLanguage, same_language_family};
+use crate::tokenizer::{TokenNormalization, Tokenizer, tokenizer_for};
+use apistos::ApiComponent;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileComparisonResult {
@@ -14,31 +23,66 @@ pub struct FileComparisonResult {
     pub file2_path: PathBuf,
 
     pub moss_result: Option<MossComparisonResult>,
+    pub normalized_moss_result: Option<MossComparisonResult>,
     pub rabin_karp_result: Option<RabinKarpComparisonResult>,
+    pub gst_result: Option<GstResult>,
     pub size_bytes_a: usize,
     pub lines_a: usize,
+    pub match_regions: Vec<MatchRegion>,
+}
+
+impl FileComparisonResult {
+    /// Score used to rank and match files: the best MOSS score blended with the
+    /// Rabin-Karp one.
+    pub fn combined_score(&self) -> f64 {
+        calculate_combined_score(
+            effective_moss_score(
+                self.moss_result.as_ref(),
+                self.normalized_moss_result.as_ref(),
+            ),
+            self.rabin_karp_result
+                .as_ref()
+                .map_or(0.0, |r| r.similarity_score),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectComparisonReport {
     pub project1_id: String,
     pub project2_id: String,
+    /// One-to-one matches between the files of both projects.
     pub file_to_file_comparisons: Vec<FileComparisonResult>,
+    /// Files of project 1 matched with no file of project 2, sorted.
+    pub unmatched_files_a: Vec<PathBuf>,
+    /// Files of project 2 matched with no file of project 1, sorted.
+    pub unmatched_files_b: Vec<PathBuf>,
     pub whole_project_moss_result: Option<MossComparisonResult>,
+    pub whole_project_normalized_moss_result: Option<MossComparisonResult>,
     pub whole_project_rabin_karp_result: Option<RabinKarpComparisonResult>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RabinKarpComparisonResult {
     pub similarity_score: f64,
+    /// Distinct k-grams of document 1 also found in document 2.
     pub kgrams_doc1_found: usize,
+    /// Distinct k-grams of document 1.
     pub total_kgrams_doc1: usize,
+    /// Share of the k-grams of document 1 also found in document 2.
+    pub containment_doc1: f64,
+    /// Share of the k-grams of document 2 also found in document 1.
+    pub containment_doc2: f64,
+    pub match_regions: Vec<MatchRegion>,
 }
 
+/// Compares the byte k-grams of two documents. K-grams whose hash is in `excluded` are
+/// ignored.
 pub fn compare_documents_rabin_karp(
     doc1_content: &str,
     doc2_content: &str,
     k_char: usize,
+    excluded: &FxHashSet<u64>,
 ) -> RabinKarpComparisonResult {
     let doc1_bytes = doc1_content.as_bytes();
     let doc2_bytes = doc2_content.as_bytes();
@@ -47,151 +91,879 @@ pub fn compare_documents_rabin_karp(
         return RabinKarpComparisonResult::default();
     }
 
-    let doc1_kgrams = generate_byte_kgrams(doc1_bytes, k_char);
-    let doc2_kgrams = generate_byte_kgrams(doc2_bytes, k_char);
+    let mut doc1_kgrams = generate_byte_kgrams(doc1_bytes, k_char);
+    let mut doc2_kgrams = generate_byte_kgrams(doc2_bytes, k_char);
+    doc1_kgrams.retain(|(hash, _)| !excluded.contains(hash));
+    doc2_kgrams.retain(|(hash, _)| !excluded.contains(hash));
 
-    let total_kgrams_doc1 = doc1_kgrams.len();
-    let similarity_score = calculate_jaccard_index(&doc1_kgrams, &doc2_kgrams);
+    // Compare k-gram contents regardless of where they occur in each document.
+    let doc1_hashes: FxHashSet<u64> = doc1_kgrams.iter().map(|&(hash, _)| hash).collect();
+    let doc2_hashes: FxHashSet<u64> = doc2_kgrams.iter().map(|&(hash, _)| hash).collect();
 
-    let intersection_size = doc1_kgrams.intersection(&doc2_kgrams).count();
+    let total_kgrams_doc1 = doc1_hashes.len();
+    let similarity_score = if doc1_hashes.is_empty() && doc2_hashes.is_empty() {
+        // Too short or entirely excluded: there is nothing left to match.
+        0.0
+    } else {
+        calculate_jaccard_index(&doc1_hashes, &doc2_hashes)
+    };
+
+    let intersection_size = doc1_hashes.intersection(&doc2_hashes).count();
+
+    let match_regions = build_match_regions(
+        doc1_content,
+        doc2_content,
+        &pair_shared_fingerprints(&doc1_kgrams, &doc2_kgrams),
+        |i| (i, i + k_char),
+        |i| (i, i + k_char),
+        k_char,
+    );
 
     RabinKarpComparisonResult {
         similarity_score,
         kgrams_doc1_found: intersection_size,
         total_kgrams_doc1,
+        containment_doc1: calculate_containment(&doc1_hashes, &doc2_hashes),
+        containment_doc2: calculate_containment(&doc2_hashes, &doc1_hashes),
+        match_regions,
     }
 }
-pub const MIN_CHAR_LENGTH_FOR_COMPARISON: usize = 20;
-const MIN_LINE_COUNT_FOR_COMPARISON: usize = 3;
-const MAX_LENGTH_RATIO_DIFFERENCE: f64 = 10.0;
+
+/// Weight of the MOSS score in [`calculate_combined_score`].
+pub const MOSS_WEIGHT: f64 = 0.6;
+/// Weight of the Rabin-Karp score in [`calculate_combined_score`].
+pub const RABIN_KARP_WEIGHT: f64 = 0.4;
+
+/// Weighted mean of a MOSS and a Rabin-Karp score, on the scale of both scores.
+pub fn calculate_combined_score(moss_score: f64, rabin_karp_score: f64) -> f64 {
+    (moss_score * MOSS_WEIGHT + rabin_karp_score * RABIN_KARP_WEIGHT)
+        / (MOSS_WEIGHT + RABIN_KARP_WEIGHT)
+}
+
+const DEFAULT_MIN_CHAR_LENGTH_FOR_COMPARISON: usize = 20;
+const DEFAULT_MIN_LINE_COUNT_FOR_COMPARISON: usize = 3;
+const DEFAULT_MAX_LENGTH_RATIO_DIFFERENCE: f64 = 10.0;
+const DEFAULT_HIGH_MATCH_THRESHOLD: f64 = 0.8;
+const DEFAULT_CLUSTER_THRESHOLD: f64 = 0.5;
 
 const DEFAULT_RABIN_KARP_K_CHAR: usize = 25;
+/// Fingerprints shared by fewer submissions are never considered common, as they are
+/// exactly what a copy between two students looks like.
+const MIN_SUBMISSIONS_FOR_COMMON_FINGERPRINT: usize = 3;
 
-pub fn compare_normalized_projects(
-    project_a: &NormalizedProject,
-    project_b: &NormalizedProject,
-) -> ProjectComparisonReport {
-    let mut file_comparisons = Vec::new();
-    let mut processed_files = FxHashSet::default();
+/// Which files of two submissions are compared with each other, based on their extensions.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, ApiComponent,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ExtensionMatching {
+    /// Only files with the same extension.
+    #[default]
+    Exact,
+    /// Files with extensions of the same language, such as `.c` and `.h`, so code moved
+    /// into a header or a renamed file is still compared.
+    LanguageFamily,
+    /// Any two text files, whatever their extensions.
+    AnyText,
+}
 
-    let blacklisted_files =
-        build_blacklist(project_a.files.keys().next().unwrap().parent().unwrap()).unwrap();
+impl ExtensionMatching {
+    /// Whether the files at `path_a` and `path_b` may be compared.
+    pub fn allows(self, path_a: &Path, path_b: &Path) -> bool {
+        match self {
+            ExtensionMatching::Exact => path_a.extension() == path_b.extension(),
+            ExtensionMatching::LanguageFamily => same_language_family(path_a, path_b),
+            ExtensionMatching::AnyText => true,
+        }
+    }
+}
 
-    for (path_a, file_a) in &project_a.files {
-        let path_a_str = path_a.to_string_lossy();
+/// Parameters of a comparison run. Every field may be omitted from a request, in which
+/// case its default is used; the effective configuration is echoed back in the report so
+/// a result can be reproduced.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, ApiComponent)]
+#[serde(default)]
+pub struct ComparisonConfig {
+    /// Number of tokens per MOSS k-gram.
+    #[serde(rename = "mossK")]
+    pub moss_k: usize,
+    /// Number of consecutive k-grams each MOSS fingerprint is winnowed from.
+    #[serde(rename = "mossWindow")]
+    pub moss_window: usize,
+    /// Number of bytes per Rabin-Karp k-gram.
+    #[serde(rename = "rabinKarpK")]
+    pub rabin_karp_k: usize,
+    /// Files with fewer characters are not compared.
+    #[serde(rename = "minCharLength")]
+    pub min_char_length: usize,
+    /// Files with fewer lines are not compared.
+    #[serde(rename = "minLineCount")]
+    pub min_line_count: usize,
+    /// Files whose lengths differ by more than this factor are not compared.
+    #[serde(rename = "maxLengthRatio")]
+    pub max_length_ratio: f64,
+    /// Combined score (0 to 1) above which a file match is logged as a high match.
+    #[serde(rename = "highMatchThreshold")]
+    pub high_match_threshold: f64,
+    /// Also compute a MOSS score with identifiers and literals replaced by placeholders,
+    /// so consistently renamed copies are still caught. The higher of the two MOSS scores
+    /// is then used for ranking.
+    #[serde(rename = "renamingResistant")]
+    pub renaming_resistant: bool,
+    /// Also compare files with Greedy String Tiling and report its coverage score.
+    #[serde(rename = "greedyStringTiling")]
+    pub greedy_string_tiling: bool,
+    /// Minimum number of tokens a Greedy String Tiling match must span.
+    #[serde(rename = "gstMinMatchLength")]
+    pub gst_min_match_length: usize,
+    /// Fingerprints found in more than this fraction (0 to 1) of the submissions of a
+    /// check are treated as boilerplate and ignored, provided at least three submissions
+    /// share them. The default of 1 keeps every fingerprint.
+    #[serde(rename = "maxFingerprintFrequency")]
+    pub max_fingerprint_frequency: f64,
+    /// Which files are compared with each other: those with the `exact` same extension,
+    /// those of the same `languageFamily`, or `anyText` files. Defaults to `exact`.
+    #[serde(rename = "extensionMatching")]
+    pub extension_matching: ExtensionMatching,
+    /// Combined score (0 to 1) from which two submissions are linked in the same cluster.
+    #[serde(rename = "clusterThreshold")]
+    pub cluster_threshold: f64,
+}
 
-        if blacklisted_files.contains(&path_a_str.to_string()) {
-            continue;
+impl Default for ComparisonConfig {
+    fn default() -> Self {
+        Self {
+            moss_k: DEFAULT_MOSS_K,
+            moss_window: DEFAULT_MOSS_WINDOW,
+            rabin_karp_k: DEFAULT_RABIN_KARP_K_CHAR,
+            min_char_length: DEFAULT_MIN_CHAR_LENGTH_FOR_COMPARISON,
+            min_line_count: DEFAULT_MIN_LINE_COUNT_FOR_COMPARISON,
+            max_length_ratio: DEFAULT_MAX_LENGTH_RATIO_DIFFERENCE,
+            high_match_threshold: DEFAULT_HIGH_MATCH_THRESHOLD,
+            renaming_resistant: false,
+            greedy_string_tiling: false,
+            gst_min_match_length: DEFAULT_GST_MIN_MATCH_LENGTH,
+            max_fingerprint_frequency: 1.0,
+            extension_matching: ExtensionMatching::default(),
+            cluster_threshold: DEFAULT_CLUSTER_THRESHOLD,
         }
+    }
+}
 
-        if processed_files.contains(path_a) {
-            continue;
+impl ComparisonConfig {
+    /// Checks that the configuration describes a meaningful comparison.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.moss_k == 0 {
+            return Err("mossK must be at least 1".to_string());
+        }
+        if self.moss_window == 0 {
+            return Err("mossWindow must be at least 1".to_string());
+        }
+        if self.rabin_karp_k == 0 {
+            return Err("rabinKarpK must be at least 1".to_string());
+        }
+        if !self.max_length_ratio.is_finite() || self.max_length_ratio < 1.0 {
+            return Err("maxLengthRatio must be a finite number of at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.high_match_threshold) {
+            return Err("highMatchThreshold must be between 0 and 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.cluster_threshold) {
+            return Err("clusterThreshold must be between 0 and 1".to_string());
         }
+        if self.gst_min_match_length == 0 {
+            return Err("gstMinMatchLength must be at least 1".to_string());
+        }
+        if !(self.max_fingerprint_frequency > 0.0 && self.max_fingerprint_frequency <= 1.0) {
+            return Err("maxFingerprintFrequency must be greater than 0 and at most 1".to_string());
+        }
+        Ok(())
+    }
+}
 
-        let mut best_match_score = 0.0;
-        let mut best_match_result = None;
+/// Fingerprints left out of every comparison, such as those of the starter code shared
+/// by all submissions (like the base files of MOSS) or those too common across a cohort
+/// (like the `-m` threshold of MOSS). Each set holds hashes computed the same way as the
+/// matching algorithm computes them under a given [`ComparisonConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct ExcludedFingerprints {
+    pub moss: FxHashSet<u64>,
+    pub normalized_moss: FxHashSet<u64>,
+    pub gst: FxHashSet<u64>,
+    pub rabin_karp: FxHashSet<u64>,
+}
 
-        for (path_b, file_b) in &project_b.files {
-            if file_a.relative_path.extension() != file_b.relative_path.extension() {
-                continue;
+impl ExcludedFingerprints {
+    /// Every fingerprint of every file of `project`, and of its concatenated sources, hashed
+    /// for `config`.
+    pub fn from_project(project: &NormalizedProject, config: &ComparisonConfig) -> Self {
+        let mut excluded = Self::default();
+        for file in project.files.values() {
+            let normalized =
+                excluded.add_token_kgrams(&file.content, tokenizer_for(&file.language), config);
+            excluded.gst.extend(
+                generate_token_kgram_hashes(&normalized, config.gst_min_match_length)
+                    .into_iter()
+                    .map(|(hash, _)| hash),
+            );
+            excluded.add_byte_kgrams(&file.content, config);
+        }
+        // The concatenated sources of two submissions are tokenized for the language
+        // dominating both, which may be none of the languages of the files above.
+        if let Some(source) = &project.concatenated_source_code {
+            for language in &WHOLE_PROJECT_LANGUAGES {
+                excluded.add_token_kgrams(source, tokenizer_for(language), config);
             }
+            excluded.add_byte_kgrams(source, config);
+        }
+        excluded
+    }
+
+    /// Adds the raw and renaming-normalized MOSS k-grams of `content`, and returns its
+    /// renaming-normalized tokens.
+    fn add_token_kgrams(
+        &mut self,
+        content: &str,
+        tokenizer: &dyn Tokenizer,
+        config: &ComparisonConfig,
+    ) -> Vec<String> {
+        let tokens = tokenizer.tokenize(content);
+        let texts = |normalization| -> Vec<String> {
+            tokens
+                .iter()
+                .map(|token| token.normalized_text(normalization).to_string())
+                .collect()
+        };
+        let raw = texts(TokenNormalization::Raw);
+        let normalized = texts(TokenNormalization::Renaming);
+        let hashes = |tokens: &[String]| {
+            generate_token_kgram_hashes(tokens, config.moss_k)
+                .into_iter()
+                .map(|(hash, _)| hash)
+        };
+        self.moss.extend(hashes(&raw));
+        self.normalized_moss.extend(hashes(&normalized));
+        normalized
+    }
 
-            let path_b_str = path_b.to_string_lossy();
+    fn add_byte_kgrams(&mut self, content: &str, config: &ComparisonConfig) {
+        self.rabin_karp.extend(
+            generate_byte_kgrams(content.as_bytes(), config.rabin_karp_k)
+                .into_iter()
+                .map(|(hash, _)| hash),
+        );
+    }
 
-            if blacklisted_files.contains(&path_b_str.to_string()) {
-                continue;
-            }
+    /// Fingerprints found in more than `config.max_fingerprint_frequency` of `projects`.
+    pub fn common_to(projects: &[NormalizedProject], config: &ComparisonConfig) -> Self {
+        let mut common = Self::default();
+        if config.max_fingerprint_frequency >= 1.0 {
+            return common;
+        }
 
-            if file_a.char_length < MIN_CHAR_LENGTH_FOR_COMPARISON
-                || file_b.char_length < MIN_CHAR_LENGTH_FOR_COMPARISON
-                || file_a.line_count < MIN_LINE_COUNT_FOR_COMPARISON
-                || file_b.line_count < MIN_LINE_COUNT_FOR_COMPARISON
-            {
-                continue;
+        let mut frequencies: [FxHashMap<u64, usize>; 4] = Default::default();
+        for project in projects {
+            let fingerprints = Self::from_project(project, config);
+            for (frequency, set) in frequencies.iter_mut().zip(fingerprints.into_sets()) {
+                for hash in set {
+                    *frequency.entry(hash).or_default() += 1;
+                }
             }
+        }
 
-            let length_ratio = file_a.char_length as f64 / file_b.char_length as f64;
-            if !(1.0 / MAX_LENGTH_RATIO_DIFFERENCE..=MAX_LENGTH_RATIO_DIFFERENCE)
-                .contains(&length_ratio)
-            {
-                continue;
-            }
+        let max_submissions = config.max_fingerprint_frequency * projects.len() as f64;
+        for (set, frequency) in common.sets_mut().into_iter().zip(frequencies) {
+            set.extend(
+                frequency
+                    .into_iter()
+                    .filter(|&(_, count)| {
+                        count >= MIN_SUBMISSIONS_FOR_COMMON_FINGERPRINT
+                            && count as f64 > max_submissions
+                    })
+                    .map(|(hash, _)| hash),
+            );
+        }
+        common
+    }
 
-            let moss_result = Some(algorithm_compare_documents_moss_like(
-                &file_a.content,
-                &file_b.content,
-            ));
+    /// Adds every fingerprint of `other`.
+    pub fn extend(&mut self, other: Self) {
+        for (set, other) in self.sets_mut().into_iter().zip(other.into_sets()) {
+            set.extend(other);
+        }
+    }
 
-            let rabin_karp_result = Some(compare_documents_rabin_karp(
-                &file_a.content,
-                &file_b.content,
-                DEFAULT_RABIN_KARP_K_CHAR,
-            ));
+    pub fn is_empty(&self) -> bool {
+        self.moss.is_empty()
+            && self.normalized_moss.is_empty()
+            && self.gst.is_empty()
+            && self.rabin_karp.is_empty()
+    }
 
-            let moss_score = moss_result.as_ref().map_or(0.0, |r| r.score);
-            let rk_score = rabin_karp_result
-                .as_ref()
-                .map_or(0.0, |r| r.similarity_score);
-            let combined_score = (moss_score * 0.6 + rk_score * 0.4) / 1.0;
-
-            if combined_score > 0.8 {
-                processed_files.insert(path_a.clone());
-                processed_files.insert(path_b.clone());
-                println!(
-                    "Found high match ({:.2}%) between {} and {}",
-                    combined_score * 100.0,
-                    path_a_str,
-                    path_b_str
-                );
-            }
+    fn sets_mut(&mut self) -> [&mut FxHashSet<u64>; 4] {
+        [
+            &mut self.moss,
+            &mut self.normalized_moss,
+            &mut self.gst,
+            &mut self.rabin_karp,
+        ]
+    }
 
-            if combined_score > best_match_score {
-                best_match_score = combined_score;
-                best_match_result = Some(FileComparisonResult {
-                    file1_path: path_a.clone(),
-                    file2_path: path_b.clone(),
-                    moss_result,
-                    rabin_karp_result,
-                    size_bytes_a: file_a.char_length,
-                    lines_a: file_a.line_count,
-                });
-            }
+    fn into_sets(self) -> [FxHashSet<u64>; 4] {
+        [self.moss, self.normalized_moss, self.gst, self.rabin_karp]
+    }
+}
+
+/// Runs the MOSS comparison in raw mode and, when enabled by `config`, in
+/// renaming-resistant mode.
+fn compare_documents_moss_modes(
+    doc1: &str,
+    doc2: &str,
+    tokenizer: &dyn Tokenizer,
+    config: &ComparisonConfig,
+    excluded: &ExcludedFingerprints,
+) -> (MossComparisonResult, Option<MossComparisonResult>) {
+    let compare = |normalization, excluded| {
+        algorithm_compare_documents_moss_like(
+            doc1,
+            doc2,
+            tokenizer,
+            normalization,
+            config.moss_k,
+            config.moss_window,
+            excluded,
+        )
+    };
+    let normalized = config
+        .renaming_resistant
+        .then(|| compare(TokenNormalization::Renaming, &excluded.normalized_moss));
+    (compare(TokenNormalization::Raw, &excluded.moss), normalized)
+}
+
+/// MOSS score used to rank a comparison: the higher of the raw and, when computed, the
+/// renaming-resistant score.
+pub fn effective_moss_score(
+    raw: Option<&MossComparisonResult>,
+    normalized: Option<&MossComparisonResult>,
+) -> f64 {
+    raw.into_iter()
+        .chain(normalized)
+        .map(|result| result.score)
+        .fold(0.0, f64::max)
+}
+
+/// Languages [`dominant_source_language`] may pick to tokenize concatenated sources.
+const WHOLE_PROJECT_LANGUAGES: [SourceLanguage; 4] = [
+    SourceLanguage::Rust,
+    SourceLanguage::Python,
+    SourceLanguage::C,
+    SourceLanguage::Text,
+];
+
+/// The language most source files of the two projects are written in, used to tokenize
+/// their concatenated source code. Falls back to plain text when there is no source file.
+fn dominant_source_language(
+    project_a: &NormalizedProject,
+    project_b: &NormalizedProject,
+) -> SourceLanguage {
+    let mut counts: FxHashMap<SourceLanguage, usize> = FxHashMap::default();
+    for file in project_a.files.values().chain(project_b.files.values()) {
+        if matches!(
+            file.language,
+            SourceLanguage::Rust | SourceLanguage::Python | SourceLanguage::C
+        ) {
+            *counts.entry(file.language.clone()).or_default() += 1;
         }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(language, count)| (*count, language.clone()))
+        .map_or(SourceLanguage::Text, |(language, _)| language)
+}
+
+pub fn compare_normalized_projects(
+    project_a: &NormalizedProject,
+    project_b: &NormalizedProject,
+    config: &ComparisonConfig,
+    excluded: &ExcludedFingerprints,
+) -> ProjectComparisonReport {
+    compare_projects(project_a, project_b, config, excluded, None)
+}
+
+/// Same as [`compare_normalized_projects`], but only compares the documents listed in
+/// `candidates`, as found by a [`FingerprintIndex`](crate::fingerprint_index::FingerprintIndex).
+/// Documents left out share no fingerprint and would have scored zero.
+pub fn compare_normalized_projects_indexed(
+    project_a: &NormalizedProject,
+    project_b: &NormalizedProject,
+    config: &ComparisonConfig,
+    excluded: &ExcludedFingerprints,
+    candidates: &CandidatePairs,
+) -> ProjectComparisonReport {
+    compare_projects(project_a, project_b, config, excluded, Some(candidates))
+}
+
+/// Files of `project`, sorted, that are not in `matched`.
+fn unmatched_files(project: &NormalizedProject, matched: &[&PathBuf]) -> Vec<PathBuf> {
+    let mut unmatched: Vec<PathBuf> = project
+        .files
+        .keys()
+        .filter(|path| !matched.contains(path))
+        .cloned()
+        .collect();
+    unmatched.sort();
+    unmatched
+}
 
-        if let Some(best_result) = best_match_result {
-            file_comparisons.push(best_result);
+/// Runs every enabled algorithm on a pair of files.
+fn compare_files(
+    file_a: &ProcessedFile,
+    file_b: &ProcessedFile,
+    config: &ComparisonConfig,
+    excluded: &ExcludedFingerprints,
+) -> FileComparisonResult {
+    // Files of different languages are only paired when any text files are compared, in
+    // which case the one with a dedicated tokenizer decides.
+    let tokenizer = match file_a.language {
+        SourceLanguage::Text | SourceLanguage::Unknown => tokenizer_for(&file_b.language),
+        _ => tokenizer_for(&file_a.language),
+    };
+    let (moss_result, normalized_moss_result) = compare_documents_moss_modes(
+        &file_a.content,
+        &file_b.content,
+        tokenizer,
+        config,
+        excluded,
+    );
+    let gst_result = config.greedy_string_tiling.then(|| {
+        compare_documents_gst(
+            &file_a.content,
+            &file_b.content,
+            tokenizer,
+            config.gst_min_match_length,
+            &excluded.gst,
+        )
+    });
+    let rabin_karp_result = compare_documents_rabin_karp(
+        &file_a.content,
+        &file_b.content,
+        config.rabin_karp_k,
+        &excluded.rabin_karp,
+    );
+
+    let match_regions = merge_match_regions(
+        [Some(&moss_result), normalized_moss_result.as_ref()]
+            .into_iter()
+            .flatten()
+            .flat_map(|r| r.match_regions.iter())
+            .chain(rabin_karp_result.match_regions.iter())
+            .chain(gst_result.iter().flat_map(|r| r.match_regions.iter()))
+            .cloned()
+            .collect(),
+    );
+    FileComparisonResult {
+        file1_path: file_a.relative_path.clone(),
+        file2_path: file_b.relative_path.clone(),
+        moss_result: Some(moss_result),
+        normalized_moss_result,
+        rabin_karp_result: Some(rabin_karp_result),
+        gst_result,
+        size_bytes_a: file_a.char_length,
+        lines_a: file_a.line_count,
+        match_regions,
+    }
+}
+
+fn compare_projects<'a>(
+    project_a: &'a NormalizedProject,
+    project_b: &'a NormalizedProject,
+    config: &ComparisonConfig,
+    excluded: &ExcludedFingerprints,
+    candidates: Option<&CandidatePairs>,
+) -> ProjectComparisonReport {
+    if candidates.is_some_and(CandidatePairs::is_empty) {
+        return ProjectComparisonReport {
+            project1_id: project_a.project_id.clone(),
+            project2_id: project_b.project_id.clone(),
+            file_to_file_comparisons: Vec::new(),
+            unmatched_files_a: unmatched_files(project_a, &[]),
+            unmatched_files_b: unmatched_files(project_b, &[]),
+            whole_project_moss_result: None,
+            whole_project_normalized_moss_result: None,
+            whole_project_rabin_karp_result: None,
+        };
+    }
+
+    // Blacklisted files were already left out by `process_project_folder`.
+    let comparable_paths = |project: &'a NormalizedProject| {
+        let mut paths: Vec<&PathBuf> = project.files.keys().collect();
+        paths.sort();
+        paths
+    };
+    let paths_a = comparable_paths(project_a);
+    let paths_b = comparable_paths(project_b);
+
+    let pairs: Vec<(usize, usize)> = paths_a
+        .iter()
+        .enumerate()
+        .flat_map(|(index_a, &path_a)| {
+            paths_b
+                .iter()
+                .enumerate()
+                .filter(move |&(_, &path_b)| {
+                    let (file_a, file_b) = (&project_a.files[path_a], &project_b.files[path_b]);
+                    config
+                        .extension_matching
+                        .allows(&file_a.relative_path, &file_b.relative_path)
+                        && candidates
+                            .is_none_or(|candidates| candidates.contains_files(path_a, path_b))
+                        && file_a.char_length >= config.min_char_length
+                        && file_b.char_length >= config.min_char_length
+                        && file_a.line_count >= config.min_line_count
+                        && file_b.line_count >= config.min_line_count
+                        && (1.0 / config.max_length_ratio..=config.max_length_ratio)
+                            .contains(&(file_a.char_length as f64 / file_b.char_length as f64))
+                })
+                .map(move |(index_b, _)| (index_a, index_b))
+        })
+        .collect();
+
+    // Pairs are compared in parallel and collected in order, so the report does not
+    // depend on the number of threads.
+    let mut comparisons: FxHashMap<(usize, usize), FileComparisonResult> = pairs
+        .into_par_iter()
+        .map(|(index_a, index_b)| {
+            let comparison = compare_files(
+                &project_a.files[paths_a[index_a]],
+                &project_b.files[paths_b[index_b]],
+                config,
+                excluded,
+            );
+            ((index_a, index_b), comparison)
+        })
+        .collect();
+
+    // Each file is matched with at most one file of the other project, maximising the
+    // total combined score of the matches. The GST similarity stands in when higher, so
+    // files only tiling finds alike, such as renamed copies, are matched too.
+    let mut scores = vec![vec![0.0; paths_b.len()]; paths_a.len()];
+    for (&(index_a, index_b), comparison) in &comparisons {
+        let gst_similarity = comparison.gst_result.as_ref().map_or(0.0, |r| r.similarity);
+        scores[index_a][index_b] = comparison.combined_score().max(gst_similarity);
+    }
+    let mut file_comparisons = Vec::new();
+    for (index_a, index_b) in max_weight_assignment(&scores).into_iter().enumerate() {
+        let Some(comparison) = index_b.and_then(|index_b| comparisons.remove(&(index_a, index_b)))
+        else {
+            continue;
+        };
+        let combined_score = comparison.combined_score();
+        if combined_score > config.high_match_threshold {
+            println!(
+                "Found high match ({:.2}%) between {} and {}",
+                combined_score * 100.0,
+                comparison.file1_path.display(),
+                comparison.file2_path.display()
+            );
         }
+        file_comparisons.push(comparison);
     }
 
-    let (whole_project_moss_result, whole_project_rabin_karp_result) = match (
+    let (
+        whole_project_moss_result,
+        whole_project_normalized_moss_result,
+        whole_project_rabin_karp_result,
+    ) = match (
         &project_a.concatenated_source_code,
         &project_b.concatenated_source_code,
     ) {
         (Some(src_a), Some(src_b))
-            if src_a.chars().count() >= MIN_CHAR_LENGTH_FOR_COMPARISON
-                && src_b.chars().count() >= MIN_CHAR_LENGTH_FOR_COMPARISON =>
+            if src_a.chars().count() >= config.min_char_length
+                && src_b.chars().count() >= config.min_char_length
+                && candidates.is_none_or(CandidatePairs::contains_concatenated) =>
         {
+            let (moss_result, normalized_moss_result) = compare_documents_moss_modes(
+                src_a,
+                src_b,
+                tokenizer_for(&dominant_source_language(project_a, project_b)),
+                config,
+                excluded,
+            );
             (
-                Some(algorithm_compare_documents_moss_like(src_a, src_b)),
+                Some(moss_result),
+                normalized_moss_result,
                 Some(compare_documents_rabin_karp(
                     src_a,
                     src_b,
-                    DEFAULT_RABIN_KARP_K_CHAR,
+                    config.rabin_karp_k,
+                    &excluded.rabin_karp,
                 )),
             )
         }
-        _ => (None, None),
+        _ => (None, None, None),
     };
 
+    let matched_a: Vec<&PathBuf> = file_comparisons.iter().map(|c| &c.file1_path).collect();
+    let matched_b: Vec<&PathBuf> = file_comparisons.iter().map(|c| &c.file2_path).collect();
     ProjectComparisonReport {
         project1_id: project_a.project_id.clone(),
         project2_id: project_b.project_id.clone(),
+        unmatched_files_a: unmatched_files(project_a, &matched_a),
+        unmatched_files_b: unmatched_files(project_b, &matched_b),
         file_to_file_comparisons: file_comparisons,
         whole_project_moss_result,
+        whole_project_normalized_moss_result,
         whole_project_rabin_karp_result,
     }
 }
 
+/// Background colours cycled through to tell matched regions apart.
+const REGION_COLOURS: [&str; 8] = [
+    "#ffd6a5", "#caffbf", "#9bf6ff", "#bdb2ff", "#ffc6ff", "#fdffb6", "#a0c4ff", "#ffadad",
+];
+
+const HTML_REPORT_STYLE: &str = "
+body { font-family: sans-serif; margin: 2rem; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2rem; }
+th, td { border: 1px solid #ccc; padding: 0.3rem 0.6rem; text-align: left; }
+td.score { text-align: right; }
+.tree, .side-by-side { display: flex; gap: 1rem; }
+.tree > div, .side-by-side > div { flex: 1; min-width: 0; }
+.source { display: flex; overflow-x: auto; border: 1px solid #ccc; }
+.source pre { margin: 0; padding: 0.5rem; font-size: 0.85rem; line-height: 1.3; }
+.gutter { color: #999; text-align: right; background: #f6f6f6; user-select: none; }
+a.match { color: inherit; text-decoration: none; border-radius: 2px; }
+a.match:target { outline: 2px solid #e63946; }
+";
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A highlighted stretch of a source file: char range, colour index and the ids of the
+/// anchor itself and of the matching region in the other file.
+struct Highlight {
+    start: usize,
+    end: usize,
+    colour: usize,
+    id: String,
+    target: String,
+}
+
+fn char_offset(line_char_starts: &[usize], line: usize, column: usize) -> usize {
+    line_char_starts
+        .get(line.saturating_sub(1))
+        .map_or(usize::MAX, |start| start + column.saturating_sub(1))
+}
+
+fn render_source(text: &str, mut highlights: Vec<Highlight>) -> String {
+    highlights.sort_by_key(|highlight| highlight.start);
+    let chars: Vec<char> = text.chars().collect();
+
+    let mut code = String::new();
+    let mut cursor = 0;
+    for highlight in highlights {
+        let start = highlight.start.max(cursor).min(chars.len());
+        let end = highlight.end.min(chars.len());
+        if start >= end {
+            continue;
+        }
+        code.push_str(&escape_html(
+            &chars[cursor..start].iter().collect::<String>(),
+        ));
+        code.push_str(&format!(
+            "<a class=\"match\" id=\"{}\" href=\"#{}\" style=\"background:{}\">{}</a>",
+            highlight.id,
+            highlight.target,
+            REGION_COLOURS[highlight.colour % REGION_COLOURS.len()],
+            escape_html(&chars[start..end].iter().collect::<String>())
+        ));
+        cursor = end;
+    }
+    code.push_str(&escape_html(&chars[cursor..].iter().collect::<String>()));
+
+    let gutter = (1..=text.lines().count().max(1))
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("<div class=\"source\"><pre class=\"gutter\">{gutter}</pre><pre>{code}</pre></div>")
+}
+
+fn line_char_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            text.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect()
+}
+
+fn render_file_tree(project: &NormalizedProject, anchors: &FxHashMap<PathBuf, String>) -> String {
+    let mut paths: Vec<&PathBuf> = project.files.keys().collect();
+    paths.sort();
+    let items: String = paths
+        .into_iter()
+        .map(|path| {
+            let name = escape_html(&path.to_string_lossy());
+            match anchors.get(path) {
+                Some(anchor) => format!("<li><a href=\"#{anchor}\">{name}</a></li>"),
+                None => format!("<li>{name}</li>"),
+            }
+        })
+        .collect();
+    format!(
+        "<div><h3>{}</h3><ul>{items}</ul></div>",
+        escape_html(&project.project_id)
+    )
+}
+
+/// Renders a self-contained HTML page comparing two submissions side by side: both file
+/// trees, the per-file scores and, for every compared file pair, the two sources with
+/// their matched regions colour-coded and linked to each other.
+pub fn render_comparison_html(
+    project_a: &NormalizedProject,
+    project_b: &NormalizedProject,
+    report: &ProjectComparisonReport,
+) -> String {
+    let percent =
+        |score: Option<f64>| score.map_or("n/a".to_string(), |s| format!("{:.1}%", s * 100.0));
+    let title = format!(
+        "{} vs {}",
+        escape_html(&project_a.project_id),
+        escape_html(&project_b.project_id)
+    );
+
+    let mut anchors_a = FxHashMap::default();
+    let mut anchors_b = FxHashMap::default();
+    let mut score_rows = String::new();
+    let mut sections = String::new();
+
+    for (pair_index, comparison) in report.file_to_file_comparisons.iter().enumerate() {
+        let pair_anchor = format!("pair-{pair_index}");
+        anchors_a.insert(comparison.file1_path.clone(), pair_anchor.clone());
+        anchors_b.insert(comparison.file2_path.clone(), pair_anchor.clone());
+
+        let moss_score = comparison.moss_result.as_ref().map(|r| r.score);
+        let normalized_moss_score = comparison.normalized_moss_result.as_ref().map(|r| r.score);
+        let rk_score = comparison
+            .rabin_karp_result
+            .as_ref()
+            .map(|r| r.similarity_score);
+        let gst_score = comparison.gst_result.as_ref().map(|r| r.similarity);
+        let combined = comparison.combined_score();
+        // Share of each file found in the other, by whichever algorithm finds the most.
+        let containment =
+            |moss: fn(&MossComparisonResult) -> f64,
+             rabin_karp: fn(&RabinKarpComparisonResult) -> f64| {
+                let moss = comparison.moss_result.as_ref().map(moss);
+                let rabin_karp = comparison.rabin_karp_result.as_ref().map(rabin_karp);
+                moss.into_iter().chain(rabin_karp).reduce(f64::max)
+            };
+        let containment_a = containment(|r| r.containment_doc1, |r| r.containment_doc1);
+        let containment_b = containment(|r| r.containment_doc2, |r| r.containment_doc2);
+        let path_a = escape_html(&comparison.file1_path.to_string_lossy());
+        let path_b = escape_html(&comparison.file2_path.to_string_lossy());
+
+        score_rows.push_str(&format!(
+            "<tr><td><a href=\"#{pair_anchor}\">{path_a}</a></td><td>{path_b}</td>\
+             <td class=\"score\">{}</td><td class=\"score\">{}</td><td class=\"score\">{}</td>\
+             <td class=\"score\">{}</td><td class=\"score\">{}</td><td class=\"score\">{}</td>\
+             <td class=\"score\">{}</td><td class=\"score\">{}</td></tr>",
+            percent(moss_score),
+            percent(normalized_moss_score),
+            percent(rk_score),
+            percent(gst_score),
+            percent(Some(combined)),
+            percent(containment_a),
+            percent(containment_b),
+            comparison.match_regions.len()
+        ));
+
+        let (Some(file_a), Some(file_b)) = (
+            project_a.files.get(&comparison.file1_path),
+            project_b.files.get(&comparison.file2_path),
+        ) else {
+            continue;
+        };
+        let starts_a = line_char_starts(&file_a.content);
+        let starts_b = line_char_starts(&file_b.content);
+        let mut highlights_a = Vec::new();
+        let mut highlights_b = Vec::new();
+        for (region_index, region) in comparison.match_regions.iter().enumerate() {
+            let id_a = format!("{pair_anchor}-region-{region_index}-a");
+            let id_b = format!("{pair_anchor}-region-{region_index}-b");
+            highlights_a.push(Highlight {
+                start: char_offset(&starts_a, region.start_line_a, region.start_column_a),
+                end: char_offset(&starts_a, region.end_line_a, region.end_column_a) + 1,
+                colour: region_index,
+                id: id_a.clone(),
+                target: id_b.clone(),
+            });
+            highlights_b.push(Highlight {
+                start: char_offset(&starts_b, region.start_line_b, region.start_column_b),
+                end: char_offset(&starts_b, region.end_line_b, region.end_column_b) + 1,
+                colour: region_index,
+                id: id_b,
+                target: id_a,
+            });
+        }
+
+        sections.push_str(&format!(
+            "<section id=\"{pair_anchor}\"><h3>{path_a} &harr; {path_b} ({})</h3>\
+             <div class=\"side-by-side\"><div>{}</div><div>{}</div></div></section>",
+            percent(Some(combined)),
+            render_source(&file_a.content, highlights_a),
+            render_source(&file_b.content, highlights_b)
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Plagiarism report: {title}</title>\n<style>{HTML_REPORT_STYLE}</style>\n</head>\n\
+         <body>\n<h1>{title}</h1>\n\
+         <p>Whole project similarity: MOSS {}, normalized MOSS {}, Rabin-Karp {}</p>\n\
+         <h2>Files</h2>\n<div class=\"tree\">{}{}</div>\n\
+         <h2>File comparisons</h2>\n<table><tr><th>{}</th><th>{}</th><th>MOSS</th>\
+         <th>Normalized MOSS</th><th>Rabin-Karp</th><th>GST</th><th>Combined</th>\
+         <th>Contained in {}</th><th>Contained in {}</th><th>Matched regions</th></tr>\
+         {score_rows}</table>\n\
+         {sections}\n</body>\n</html>\n",
+        percent(report.whole_project_moss_result.as_ref().map(|r| r.score)),
+        percent(
+            report
+                .whole_project_normalized_moss_result
+                .as_ref()
+                .map(|r| r.score)
+        ),
+        percent(
+            report
+                .whole_project_rabin_karp_result
+                .as_ref()
+                .map(|r| r.similarity_score)
+        ),
+        render_file_tree(project_a, &anchors_a),
+        render_file_tree(project_b, &anchors_b),
+        escape_html(&project_a.project_id),
+        escape_html(&project_b.project_id),
+        // File A's containment is its share found in B, and the other way around.
+        escape_html(&project_b.project_id),
+        escape_html(&project_a.project_id),
+    )
+}
+
 #[cfg(test)]
 mod project_comparison_logic_tests {
     use crate::project_processor::{NormalizedProject, ProcessedFile, SourceLanguage};
@@ -424,4 +1196,573 @@ mod project_comparison_logic_tests {
         assert_eq!(selected[0], PathBuf::from("valid1.txt"));
         assert_eq!(selected[1], PathBuf::from("valid2.txt"));
     }
+
+    #[test]
+    fn test_render_comparison_html_highlights_and_links_regions() {
+        use super::{
+            ComparisonConfig, ExcludedFingerprints, compare_normalized_projects,
+            render_comparison_html,
+        };
+
+        let shared = "int sum(int *values, int n) {\n    int total = 0;\n    for (int i = 0; i < n; i++) {\n        total += values[i];\n    }\n    return total;\n}\n";
+        let (path_a, file_a) = mock_file("sum.c", &format!("#include <stdio.h>\n{shared}"));
+        let (path_b, file_b) = mock_file("total.c", &format!("/* <mine> */\n{shared}"));
+        let (path_c, file_c) = mock_file("notes.txt", "unrelated notes\nabout nothing\nat all\n");
+        let proj_a = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::from_iter([(path_a, file_a), (path_c, file_c)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let proj_b = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path_b, file_b)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+
+        let report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        assert_eq!(report.file_to_file_comparisons.len(), 1);
+        let html = render_comparison_html(&proj_a, &proj_b, &report);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>A vs B</h1>"));
+        assert!(html.contains("<li><a href=\"#pair-0\">sum.c</a></li>"));
+        assert!(html.contains("<li>notes.txt</li>"));
+        assert!(html.contains("/* &lt;mine&gt; */"));
+        assert!(html.contains("id=\"pair-0-region-0-a\" href=\"#pair-0-region-0-b\""));
+        assert!(html.contains("id=\"pair-0-region-0-b\" href=\"#pair-0-region-0-a\""));
+        assert!(!html.contains("<mine>"));
+        let combined = report.file_to_file_comparisons[0].combined_score();
+        assert!(html.contains(&format!("({:.1}%)</h3>", combined * 100.0)));
+        assert!(html.contains("<th>Normalized MOSS</th><th>Rabin-Karp</th><th>GST</th>"));
+        assert!(html.contains("<th>Contained in B</th><th>Contained in A</th>"));
+        // Without renaming resistance nor tiling, those scores were not computed.
+        assert_eq!(html.matches("<td class=\"score\">n/a</td>").count(), 2);
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_handles_projects_without_files() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+
+        let empty = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::default(),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let (path, file) = mock_file("main.c", "int main(void) {\n    return 0;\n}\n");
+        let other = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path.clone(), file)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+
+        let report = compare_normalized_projects(
+            &empty,
+            &other,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        assert!(report.file_to_file_comparisons.is_empty());
+        assert!(report.unmatched_files_a.is_empty());
+        assert_eq!(report.unmatched_files_b, vec![path]);
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_matches_files_one_to_one() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+
+        let sum = "int sum(int *values, int n) {\n    int total = 0;\n    for (int i = 0; i < n; i++) {\n        total += values[i];\n    }\n    return total;\n}\n";
+        let (path_a1, file_a1) = mock_file("sum.c", sum);
+        let (path_a2, file_a2) = mock_file("sum_copy.c", &format!("/* backup */\n{sum}"));
+        let (path_a3, file_a3) = mock_file("notes.txt", "some notes\nabout the\nproject\n");
+        let (path_b1, file_b1) = mock_file("total.c", sum);
+        let proj_a = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::from_iter([
+                (path_a1, file_a1),
+                (path_a2, file_a2),
+                (path_a3, file_a3),
+            ]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let proj_b = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path_b1, file_b1)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+
+        let report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        // Both copies in A best match total.c, but it can only be matched once: with the
+        // exact copy.
+        assert_eq!(report.file_to_file_comparisons.len(), 1);
+        assert_eq!(
+            report.file_to_file_comparisons[0].file1_path,
+            PathBuf::from("sum.c")
+        );
+        assert_eq!(
+            report.file_to_file_comparisons[0].file2_path,
+            PathBuf::from("total.c")
+        );
+        assert_eq!(
+            report.unmatched_files_a,
+            vec![PathBuf::from("notes.txt"), PathBuf::from("sum_copy.c")]
+        );
+        assert!(report.unmatched_files_b.is_empty());
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_honours_extension_matching() {
+        use super::{
+            ComparisonConfig, ExcludedFingerprints, ExtensionMatching, compare_normalized_projects,
+        };
+
+        let sum = "int sum(int *values, int n) {\n    int total = 0;\n    for (int i = 0; i < n; i++) {\n        total += values[i];\n    }\n    return total;\n}\n";
+        let notes = "some notes\nabout the\nproject\n";
+        let (path_a1, file_a1) = mock_file("sum.c", sum);
+        let (path_a2, file_a2) = mock_file("notes.txt", notes);
+        let (path_b1, file_b1) = mock_file("sum.h", sum);
+        let (path_b2, file_b2) = mock_file("README", notes);
+        let proj_a = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::from_iter([(path_a1, file_a1), (path_a2, file_a2)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let proj_b = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path_b1, file_b1), (path_b2, file_b2)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let matched_pairs = |extension_matching| {
+            let config = ComparisonConfig {
+                extension_matching,
+                ..ComparisonConfig::default()
+            };
+            let report = compare_normalized_projects(
+                &proj_a,
+                &proj_b,
+                &config,
+                &ExcludedFingerprints::default(),
+            );
+            let mut pairs: Vec<(PathBuf, PathBuf)> = report
+                .file_to_file_comparisons
+                .into_iter()
+                .map(|c| (c.file1_path, c.file2_path))
+                .collect();
+            pairs.sort();
+            pairs
+        };
+
+        assert!(matched_pairs(ExtensionMatching::Exact).is_empty());
+        assert_eq!(
+            matched_pairs(ExtensionMatching::LanguageFamily),
+            vec![(PathBuf::from("sum.c"), PathBuf::from("sum.h"))]
+        );
+        assert_eq!(
+            matched_pairs(ExtensionMatching::AnyText),
+            vec![
+                (PathBuf::from("notes.txt"), PathBuf::from("README")),
+                (PathBuf::from("sum.c"), PathBuf::from("sum.h")),
+            ]
+        );
+
+        let config: ComparisonConfig =
+            serde_json::from_str(r#"{"extensionMatching": "anyText"}"#).unwrap();
+        assert_eq!(config.extension_matching, ExtensionMatching::AnyText);
+    }
+
+    #[test]
+    fn test_comparison_config_fills_missing_fields_with_defaults() {
+        use super::{ComparisonConfig, ExtensionMatching};
+
+        let config: ComparisonConfig =
+            serde_json::from_str(r#"{"mossK": 6, "highMatchThreshold": 0.9}"#).unwrap();
+        assert_eq!(
+            config,
+            ComparisonConfig {
+                moss_k: 6,
+                high_match_threshold: 0.9,
+                ..ComparisonConfig::default()
+            }
+        );
+        assert_eq!(config.extension_matching, ExtensionMatching::Exact);
+        assert!(config.validate().is_ok());
+        assert!(ComparisonConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_comparison_config_rejects_meaningless_values() {
+        use super::ComparisonConfig;
+
+        let invalid = [
+            ComparisonConfig {
+                moss_k: 0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                moss_window: 0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                rabin_karp_k: 0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                max_length_ratio: 0.5,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                high_match_threshold: 1.5,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                cluster_threshold: -0.1,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                gst_min_match_length: 0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                max_fingerprint_frequency: 0.0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                max_fingerprint_frequency: f64::NAN,
+                ..ComparisonConfig::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{config:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_honours_config() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+
+        let content = "int main(void) {\n    int x = 1;\n    return x;\n}\n";
+        let (path_a, file_a) = mock_file("main.c", content);
+        let (path_b, file_b) = mock_file("copy.c", content);
+        let proj_a = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::from_iter([(path_a, file_a)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let proj_b = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path_b, file_b)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+
+        let default_report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        assert_eq!(default_report.file_to_file_comparisons.len(), 1);
+
+        let strict = ComparisonConfig {
+            min_line_count: 10,
+            ..ComparisonConfig::default()
+        };
+        let strict_report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &strict,
+            &ExcludedFingerprints::default(),
+        );
+        assert!(strict_report.file_to_file_comparisons.is_empty());
+
+        let coarse = ComparisonConfig {
+            rabin_karp_k: 8,
+            ..ComparisonConfig::default()
+        };
+        let coarse_report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &coarse,
+            &ExcludedFingerprints::default(),
+        );
+        let rabin_karp = coarse_report.file_to_file_comparisons[0]
+            .rabin_karp_result
+            .as_ref()
+            .unwrap();
+        assert_eq!(rabin_karp.total_kgrams_doc1, content.len() - 8 + 1);
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_reports_renaming_resistant_scores() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+        use crate::project_processor::SourceLanguage;
+
+        let c_file = |path: &str, content: &str| {
+            let (path, mut file) = mock_file(path, content);
+            file.language = SourceLanguage::C;
+            (path, file)
+        };
+        let (path_a, file_a) = c_file(
+            "main.c",
+            "int main(void) {\n    int count = 3;\n    while (count > 0) {\n        count = count - 1;\n    }\n    return count;\n}\n",
+        );
+        let (path_b, file_b) = c_file(
+            "copy.c",
+            "int main(void) {\n    int remaining = 9;\n    while (remaining > 0) {\n        remaining = remaining - 2;\n    }\n    return remaining;\n}\n",
+        );
+        let proj_a = NormalizedProject {
+            project_id: "A".into(),
+            files: FxHashMap::from_iter([(path_a, file_a)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+        let proj_b = NormalizedProject {
+            project_id: "B".into(),
+            files: FxHashMap::from_iter([(path_b, file_b)]),
+            concatenated_source_code: None,
+            concatenated_source_hash: None,
+        };
+
+        let raw_report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        assert!(
+            raw_report.file_to_file_comparisons[0]
+                .normalized_moss_result
+                .is_none()
+        );
+        assert!(raw_report.file_to_file_comparisons[0].gst_result.is_none());
+
+        let config = ComparisonConfig {
+            renaming_resistant: true,
+            greedy_string_tiling: true,
+            ..ComparisonConfig::default()
+        };
+        let report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &config,
+            &ExcludedFingerprints::default(),
+        );
+        let comparison = &report.file_to_file_comparisons[0];
+        let raw_score = comparison.moss_result.as_ref().unwrap().score;
+        let normalized_score = comparison.normalized_moss_result.as_ref().unwrap().score;
+        assert!(raw_score < 0.5);
+        assert_eq!(normalized_score, 1.0);
+        assert_eq!(comparison.gst_result.as_ref().unwrap().similarity, 1.0);
+    }
+
+    #[test]
+    fn test_compare_documents_rabin_karp_finds_shifted_copies() {
+        use super::compare_documents_rabin_karp;
+        use rustc_hash::FxHashSet;
+
+        let copied = "for (int i = 0; i < n; i++) { total += values[i]; }";
+        let host = format!(
+            "/* a much longer file that embeds the copied loop */\n{copied}\nreturn total;\n"
+        );
+        let result = compare_documents_rabin_karp(copied, &host, 10, &FxHashSet::default());
+
+        assert_eq!(result.containment_doc1, 1.0);
+        assert_eq!(result.kgrams_doc1_found, result.total_kgrams_doc1);
+        assert!(result.containment_doc2 < 0.7);
+        assert!(result.similarity_score < result.containment_doc1);
+    }
+
+    #[test]
+    fn test_compare_normalized_projects_ignores_template_code() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+        use crate::project_processor::SourceLanguage;
+
+        let c_project = |id: &str, content: &str| {
+            let (path, mut file) = mock_file("main.c", content);
+            file.language = SourceLanguage::C;
+            NormalizedProject {
+                project_id: id.into(),
+                files: FxHashMap::from_iter([(path, file)]),
+                concatenated_source_code: None,
+                concatenated_source_hash: None,
+            }
+        };
+        let starter = "#include <stdio.h>\n\nint read_input(int *values, int capacity) {\n    int n = 0;\n    while (n < capacity && scanf(\"%d\", &values[n]) == 1) {\n        n++;\n    }\n    return n;\n}\n";
+        let template = c_project("template", starter);
+        let proj_a = c_project("A", starter);
+        let proj_b = c_project("B", starter);
+        let config = ComparisonConfig {
+            renaming_resistant: true,
+            greedy_string_tiling: true,
+            ..ComparisonConfig::default()
+        };
+
+        let report = compare_normalized_projects(
+            &proj_a,
+            &proj_b,
+            &config,
+            &ExcludedFingerprints::default(),
+        );
+        let comparison = &report.file_to_file_comparisons[0];
+        assert_eq!(comparison.moss_result.as_ref().unwrap().score, 1.0);
+        assert!(!comparison.match_regions.is_empty());
+
+        let excluded = ExcludedFingerprints::from_project(&template, &config);
+        assert!(!excluded.is_empty());
+        let report = compare_normalized_projects(&proj_a, &proj_b, &config, &excluded);
+        // Nothing but template code matches, so the files are not reported as a match.
+        assert!(report.file_to_file_comparisons.is_empty());
+    }
+
+    #[test]
+    fn test_template_code_is_ignored_in_mixed_language_projects() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+
+        let project = |id: &str, files: &[(&str, &str, SourceLanguage)]| {
+            let mut files: Vec<(PathBuf, ProcessedFile)> = files
+                .iter()
+                .map(|(path, content, language)| {
+                    let (path, mut file) = mock_file(path, content);
+                    file.language = language.clone();
+                    (path, file)
+                })
+                .collect();
+            files.sort_by(|a, b| a.0.cmp(&b.0));
+            let concatenated = files
+                .iter()
+                .map(|(_, file)| file.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n---FILE_SEPARATOR---\n\n");
+            NormalizedProject {
+                project_id: id.into(),
+                files: files.into_iter().collect(),
+                concatenated_source_code: Some(concatenated),
+                concatenated_source_hash: None,
+            }
+        };
+        // Comments and floor divisions are read differently by the C tokenizer.
+        let starter = "# Reads the numbers given on standard input, one per line.\nimport sys\n\ndef read_numbers():\n    # Blank lines are skipped.\n    return [int(line) for line in sys.stdin if line.strip()]\n\ndef halves(numbers):\n    return [n // 2 for n in numbers]\n";
+        let template = project("template", &[("run.py", starter, SourceLanguage::Python)]);
+        // Mostly C, so the concatenated sources are tokenized as C.
+        let proj_a = project(
+            "A",
+            &[
+                ("run.py", starter, SourceLanguage::Python),
+                (
+                    "sum.c",
+                    "long sum(const long *xs, size_t len) {\n    long acc = 0;\n    while (len--) acc += *xs++;\n    return acc;\n}\n",
+                    SourceLanguage::C,
+                ),
+                (
+                    "max.c",
+                    "#define MAX(a, b) ((a) > (b) ? (a) : (b))\nstatic unsigned counter;\n",
+                    SourceLanguage::C,
+                ),
+            ],
+        );
+        let proj_b = project(
+            "B",
+            &[
+                ("run.py", starter, SourceLanguage::Python),
+                (
+                    "mean.c",
+                    "double mean(double first, double second) {\n    return (first + second) / 2.0;\n}\n",
+                    SourceLanguage::C,
+                ),
+                (
+                    "print.c",
+                    "#include <stdio.h>\nvoid show(const char *text) { puts(text); }\n",
+                    SourceLanguage::C,
+                ),
+            ],
+        );
+        let config = ComparisonConfig::default();
+        let whole_project_score = |excluded: &ExcludedFingerprints| {
+            compare_normalized_projects(&proj_a, &proj_b, &config, excluded)
+                .whole_project_moss_result
+                .unwrap()
+                .score
+        };
+
+        let unfiltered = whole_project_score(&ExcludedFingerprints::default());
+        assert!(unfiltered > 0.25, "{unfiltered}");
+
+        let excluded = ExcludedFingerprints::from_project(&template, &config);
+        let read_as_c = project("template", &[("run.py", starter, SourceLanguage::C)]);
+        let read_as_c = ExcludedFingerprints::from_project(&read_as_c, &config);
+        assert!(read_as_c.moss.is_subset(&excluded.moss));
+        assert!(
+            read_as_c
+                .normalized_moss
+                .is_subset(&excluded.normalized_moss)
+        );
+        // Only the k-grams spanning a file separator are left.
+        let filtered = whole_project_score(&excluded);
+        assert!(filtered < 0.1, "{filtered}");
+    }
+
+    #[test]
+    fn test_common_fingerprints_are_excluded_but_pairwise_copies_are_kept() {
+        use super::{ComparisonConfig, ExcludedFingerprints, compare_normalized_projects};
+
+        let boilerplate =
+            "#include <stdio.h>\n#include <stdlib.h>\n\nint main(int argc, char **argv) {\n";
+        let project = |id: &str, body: &str| {
+            let (path, file) = mock_file("main.c", &format!("{boilerplate}{body}}}\n"));
+            NormalizedProject {
+                project_id: id.into(),
+                files: FxHashMap::from_iter([(path, file)]),
+                concatenated_source_code: None,
+                concatenated_source_hash: None,
+            }
+        };
+        let copied = "    long total = 0;\n    for (int i = 1; i < argc; i++) total += atol(argv[i]);\n    printf(\"%ld\\n\", total);\n";
+        let projects = [
+            project("A", copied),
+            project("B", copied),
+            project("C", "    puts(\"hello world, this is my own program\");\n"),
+            project("D", "    return argc > 1 ? EXIT_FAILURE : EXIT_SUCCESS;\n"),
+        ];
+
+        let keep_all = ComparisonConfig::default();
+        assert!(ExcludedFingerprints::common_to(&projects, &keep_all).is_empty());
+
+        let config = ComparisonConfig {
+            max_fingerprint_frequency: 0.5,
+            ..ComparisonConfig::default()
+        };
+        let common = ExcludedFingerprints::common_to(&projects, &config);
+        assert!(!common.moss.is_empty());
+        assert!(!common.rabin_karp.is_empty());
+        let score = |excluded: &ExcludedFingerprints, a: usize, b: usize| {
+            compare_normalized_projects(&projects[a], &projects[b], &config, excluded)
+                .file_to_file_comparisons
+                .first()
+                .and_then(|comparison| comparison.moss_result.as_ref())
+                .map_or(0.0, |result| result.score)
+        };
+        let unfiltered = ExcludedFingerprints::default();
+        assert!(score(&unfiltered, 2, 3) > 0.0);
+        assert_eq!(score(&common, 2, 3), 0.0);
+        // Shared by only two submissions, the copied body is never common.
+        assert_eq!(score(&common, 0, 1), 1.0);
+    }
 }
@@ -0,0 +1,414 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+/// Entries smaller than this are never rejected for their compression ratio: tiny
+/// repetitive files compress extremely well without being a threat.
+const MIN_RATIO_CHECKED_BYTES: u64 = 1024 * 1024;
+
+/// Caps applied when extracting an untrusted zip archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractionLimits {
+    /// Most entries, files and directories alike, an archive may hold.
+    pub max_entries: usize,
+    /// Most bytes extracted from a whole archive.
+    pub max_total_bytes: u64,
+    /// Most bytes extracted from a single file.
+    pub max_file_bytes: u64,
+    /// Highest ratio between the extracted and compressed sizes of a file.
+    pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 20_000,
+            max_total_bytes: 512 * 1024 * 1024,
+            max_file_bytes: 32 * 1024 * 1024,
+            max_compression_ratio: 100,
+        }
+    }
+}
+
+impl ExtractionLimits {
+    /// Limits read from `MAX_ARCHIVE_ENTRIES`, `MAX_EXTRACTED_BYTES`,
+    /// `MAX_EXTRACTED_FILE_BYTES` and `MAX_COMPRESSION_RATIO`, each falling back to its
+    /// default when unset or invalid.
+    pub fn from_env() -> Self {
+        fn var_or<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+        let defaults = Self::default();
+        Self {
+            max_entries: var_or("MAX_ARCHIVE_ENTRIES", defaults.max_entries),
+            max_total_bytes: var_or("MAX_EXTRACTED_BYTES", defaults.max_total_bytes),
+            max_file_bytes: var_or("MAX_EXTRACTED_FILE_BYTES", defaults.max_file_bytes),
+            max_compression_ratio: var_or("MAX_COMPRESSION_RATIO", defaults.max_compression_ratio),
+        }
+    }
+}
+
+/// Why an archive could not be extracted.
+#[derive(Debug)]
+pub enum ExtractionError {
+    TooManyEntries {
+        entries: usize,
+        limit: usize,
+    },
+    /// The entry name is absolute or climbs out of the extraction directory.
+    UnsafePath {
+        name: String,
+    },
+    Symlink {
+        name: String,
+    },
+    FileTooLarge {
+        name: String,
+        limit: u64,
+    },
+    ArchiveTooLarge {
+        limit: u64,
+    },
+    CompressionRatioTooHigh {
+        name: String,
+        limit: u64,
+    },
+    Zip(ZipError),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl fmt::Display for ExtractionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtractionError::TooManyEntries { entries, limit } => {
+                write!(
+                    f,
+                    "Archive holds {entries} entries, more than the {limit} allowed"
+                )
+            }
+            ExtractionError::UnsafePath { name } => {
+                write!(
+                    f,
+                    "Entry '{name}' points outside of the extraction directory"
+                )
+            }
+            ExtractionError::Symlink { name } => {
+                write!(f, "Entry '{name}' is a symbolic link, which is not allowed")
+            }
+            ExtractionError::FileTooLarge { name, limit } => {
+                write!(f, "Entry '{name}' extracts to more than {limit} bytes")
+            }
+            ExtractionError::ArchiveTooLarge { limit } => {
+                write!(f, "Archive extracts to more than {limit} bytes")
+            }
+            ExtractionError::CompressionRatioTooHigh { name, limit } => {
+                write!(
+                    f,
+                    "Entry '{name}' is compressed more than {limit} times, which looks like a zip bomb"
+                )
+            }
+            ExtractionError::Zip(e) => write!(f, "Failed to read zip archive: {e}"),
+            ExtractionError::Io { path, source } => {
+                write!(f, "Failed to extract {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for ExtractionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExtractionError::Zip(source) => Some(source),
+            ExtractionError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Path of the entry `name` relative to the extraction directory, or `None` when it is
+/// absolute, holds a parent component or a NUL byte.
+fn relative_entry_path(name: &str) -> Option<PathBuf> {
+    // Archives created on Windows may use backslashes as separators.
+    let name = name.replace('\\', "/");
+    let path = PathBuf::from(&name);
+    let is_contained = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    (is_contained && !name.contains('\0')).then_some(path)
+}
+
+/// Extracts the untrusted `archive` into `destination`, refusing entries that would end
+/// up outside of it, symbolic links, and archives exceeding any of `limits`.
+///
+/// Sizes declared by the archive are checked up front, then enforced while extracting,
+/// since a crafted archive can lie about them. On error, `destination` may hold the
+/// entries extracted so far.
+pub fn extract_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    destination: &Path,
+    limits: &ExtractionLimits,
+) -> Result<(), ExtractionError> {
+    if archive.len() > limits.max_entries {
+        return Err(ExtractionError::TooManyEntries {
+            entries: archive.len(),
+            limit: limits.max_entries,
+        });
+    }
+
+    let mut total_bytes: u64 = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(ExtractionError::Zip)?;
+        let name = entry.name().to_string();
+        let Some(relative_path) = relative_entry_path(&name) else {
+            return Err(ExtractionError::UnsafePath { name });
+        };
+        if entry.is_symlink() {
+            return Err(ExtractionError::Symlink { name });
+        }
+        let path = destination.join(relative_path);
+        let io_error = |source| ExtractionError::Io {
+            path: path.clone(),
+            source,
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(&path).map_err(io_error)?;
+            continue;
+        }
+
+        let compressed_size = entry.compressed_size();
+        let exceeds_ratio = |size: u64| {
+            size > MIN_RATIO_CHECKED_BYTES
+                && size > compressed_size.saturating_mul(limits.max_compression_ratio)
+        };
+        if entry.size() > limits.max_file_bytes {
+            return Err(ExtractionError::FileTooLarge {
+                name,
+                limit: limits.max_file_bytes,
+            });
+        }
+        if exceeds_ratio(entry.size()) {
+            return Err(ExtractionError::CompressionRatioTooHigh {
+                name,
+                limit: limits.max_compression_ratio,
+            });
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let mut file = File::create(&path).map_err(io_error)?;
+        // Reading one byte past the allowance tells a file that reaches it from one that
+        // exceeds it.
+        let allowance = limits
+            .max_file_bytes
+            .min(limits.max_total_bytes.saturating_sub(total_bytes));
+        let written =
+            io::copy(&mut (&mut entry).take(allowance + 1), &mut file).map_err(io_error)?;
+        if written > limits.max_file_bytes {
+            return Err(ExtractionError::FileTooLarge {
+                name,
+                limit: limits.max_file_bytes,
+            });
+        }
+        if written > allowance {
+            return Err(ExtractionError::ArchiveTooLarge {
+                limit: limits.max_total_bytes,
+            });
+        }
+        if exceeds_ratio(written) {
+            return Err(ExtractionError::CompressionRatioTooHigh {
+                name,
+                limit: limits.max_compression_ratio,
+            });
+        }
+        total_bytes += written;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::CompressionMethod;
+    use zip::write::SimpleFileOptions;
+
+    fn extract(
+        archive: Vec<u8>,
+        limits: &ExtractionLimits,
+    ) -> (tempfile::TempDir, Result<(), ExtractionError>) {
+        let root = tempfile::tempdir().unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        let result = extract_archive(&mut archive, &root.path().join("submission"), limits);
+        (root, result)
+    }
+
+    fn archive_of(files: &[(&str, &[u8])], options: SimpleFileOptions) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Rewrites the uncompressed size declared by the local and central headers of every
+    /// entry of `archive` to `size`.
+    fn with_declared_size(mut archive: Vec<u8>, size: u32) -> Vec<u8> {
+        for (signature, size_offset) in [(0x04034b50u32, 22), (0x02014b50, 24)] {
+            let headers: Vec<usize> = archive
+                .windows(4)
+                .enumerate()
+                .filter(|(_, bytes)| *bytes == signature.to_le_bytes())
+                .map(|(offset, _)| offset)
+                .collect();
+            for header in headers {
+                let field = header + size_offset;
+                archive[field..field + 4].copy_from_slice(&size.to_le_bytes());
+            }
+        }
+        archive
+    }
+
+    fn stored(files: &[(&str, &[u8])]) -> Vec<u8> {
+        archive_of(
+            files,
+            SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+        )
+    }
+
+    #[test]
+    fn test_extract_archive_extracts_nested_files() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("empty/", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("./src\\main.py", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"print(1)\n").unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let (root, result) = extract(archive, &ExtractionLimits::default());
+        result.unwrap();
+        let submission = root.path().join("submission");
+        assert!(submission.join("empty").is_dir());
+        assert_eq!(
+            fs::read_to_string(submission.join("src/main.py")).unwrap(),
+            "print(1)\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_archive_rejects_paths_leaving_the_destination() {
+        for name in [
+            "../escape.py",
+            "src/../../escape.py",
+            "..\\escape.py",
+            "/tmp/escape.py",
+        ] {
+            let (root, result) = extract(
+                stored(&[(name, b"print(1)\n")]),
+                &ExtractionLimits::default(),
+            );
+            assert!(
+                matches!(result, Err(ExtractionError::UnsafePath { .. })),
+                "{name}"
+            );
+            assert!(!root.path().join("escape.py").exists());
+        }
+    }
+
+    #[test]
+    fn test_extract_archive_rejects_symlinks() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_symlink("passwd", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let (root, result) = extract(archive, &ExtractionLimits::default());
+        assert!(matches!(result, Err(ExtractionError::Symlink { .. })));
+        assert!(!root.path().join("submission/passwd").exists());
+    }
+
+    #[test]
+    fn test_extract_archive_enforces_entry_and_size_limits() {
+        let limits = ExtractionLimits {
+            max_entries: 2,
+            max_total_bytes: 100,
+            max_file_bytes: 60,
+            max_compression_ratio: 100,
+        };
+
+        let (_root, result) = extract(
+            stored(&[("a.py", b""), ("b.py", b""), ("c.py", b"")]),
+            &limits,
+        );
+        assert!(matches!(
+            result,
+            Err(ExtractionError::TooManyEntries {
+                entries: 3,
+                limit: 2
+            })
+        ));
+
+        let (_root, result) = extract(stored(&[("big.py", &[b'a'; 61])]), &limits);
+        assert!(matches!(
+            result,
+            Err(ExtractionError::FileTooLarge { ref name, limit: 60 }) if name == "big.py"
+        ));
+
+        let (_root, result) = extract(
+            stored(&[("a.py", &[b'a'; 60]), ("b.py", &[b'b'; 41])]),
+            &limits,
+        );
+        assert!(matches!(
+            result,
+            Err(ExtractionError::ArchiveTooLarge { limit: 100 })
+        ));
+
+        let (_root, result) = extract(
+            stored(&[("a.py", &[b'a'; 60]), ("b.py", &[b'b'; 40])]),
+            &limits,
+        );
+        result.unwrap();
+    }
+
+    #[test]
+    fn test_extract_archive_rejects_zip_bombs() {
+        let bomb = archive_of(
+            &[("bomb.txt", &vec![0; 4 * 1024 * 1024])],
+            SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+        );
+
+        let (_root, result) = extract(bomb.clone(), &ExtractionLimits::default());
+        assert!(matches!(
+            result,
+            Err(ExtractionError::CompressionRatioTooHigh { .. })
+        ));
+
+        // Sizes declared by a crafted archive cannot be trusted, so limits hold while
+        // extracting too.
+        let (_root, result) = extract(
+            with_declared_size(bomb, 1000),
+            &ExtractionLimits {
+                max_file_bytes: 1024 * 1024,
+                ..ExtractionLimits::default()
+            },
+        );
+        assert!(matches!(result, Err(ExtractionError::FileTooLarge { .. })));
+    }
+}
@@ -0,0 +1,533 @@
+use crate::algorithm::{generate_byte_kgrams, generate_token_kgram_hashes, moss_fingerprints};
+use crate::comparison_orchestrator::{ComparisonConfig, ExcludedFingerprints, ExtensionMatching};
+use crate::project_processor::{NormalizedProject, SourceLanguage};
+use crate::tokenizer::{TokenNormalization, Tokenizer, tokenizer_for};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::path::{Path, PathBuf};
+
+/// One language per tokenizer, for documents that may be compared under any of them: the
+/// concatenated sources of two submissions, whose tokenizer depends on the files of both,
+/// and files when any text files are compared.
+const TOKENIZER_LANGUAGES: [SourceLanguage; 4] = [
+    SourceLanguage::Rust,
+    SourceLanguage::Python,
+    SourceLanguage::C,
+    SourceLanguage::Text,
+];
+
+/// A document of a submission: one of its files or its concatenated sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Document {
+    /// Position of the file among the sorted paths of the submission.
+    File(usize),
+    Concatenated,
+}
+
+/// First occurrence of a fingerprint in a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    /// Position of the submission among those the index was built from.
+    pub project: usize,
+    pub document: Document,
+    /// Token index of a MOSS fingerprint, or byte offset of a Rabin-Karp k-gram.
+    pub position: usize,
+}
+
+/// Documents of two submissions that share at least one fingerprint, and are therefore
+/// worth comparing.
+#[derive(Debug, Clone, Default)]
+pub struct CandidatePairs {
+    files: FxHashMap<PathBuf, FxHashSet<PathBuf>>,
+    concatenated: bool,
+}
+
+impl CandidatePairs {
+    /// Whether file `path_a` of the first submission shares a fingerprint with file
+    /// `path_b` of the second one.
+    pub fn contains_files(&self, path_a: &Path, path_b: &Path) -> bool {
+        self.files
+            .get(path_a)
+            .is_some_and(|paths_b| paths_b.contains(path_b))
+    }
+
+    /// Whether the concatenated sources of both submissions share a fingerprint.
+    pub fn contains_concatenated(&self) -> bool {
+        self.concatenated
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && !self.concatenated
+    }
+}
+
+/// Inverted index from every fingerprint of a check to the submissions, documents and
+/// positions containing it, built once so that only documents sharing at least one
+/// fingerprint are compared instead of every pair.
+///
+/// Fingerprints are computed exactly as the comparisons compute them, minus the excluded
+/// ones: the winnowed MOSS fingerprints (raw, and normalized when `renamingResistant` is
+/// set), every Rabin-Karp k-gram and, when `greedyStringTiling` is set, every
+/// renaming-normalized k-gram of `gstMinMatchLength` tokens of each file. A pair left out
+/// would therefore have scored zero.
+pub struct FingerprintIndex {
+    files: Vec<Vec<PathBuf>>,
+    postings: FxHashMap<u64, Vec<Posting>>,
+    /// Documents too short for a single MOSS k-gram, which MOSS deems identical.
+    kgramless: Vec<(usize, Document)>,
+}
+
+impl FingerprintIndex {
+    pub fn build(
+        projects: &[NormalizedProject],
+        config: &ComparisonConfig,
+        excluded: &ExcludedFingerprints,
+    ) -> Self {
+        let mut index = Self {
+            files: Vec::with_capacity(projects.len()),
+            postings: FxHashMap::default(),
+            kgramless: Vec::new(),
+        };
+        let all_tokenizers = TOKENIZER_LANGUAGES.each_ref().map(tokenizer_for);
+        let any_text = config.extension_matching == ExtensionMatching::AnyText;
+
+        for (project_index, project) in projects.iter().enumerate() {
+            let mut paths: Vec<PathBuf> = project.files.keys().cloned().collect();
+            paths.sort();
+            for (file_index, path) in paths.iter().enumerate() {
+                let file = &project.files[path];
+                let own_tokenizer = [tokenizer_for(&file.language)];
+                index.add_document(
+                    project_index,
+                    Document::File(file_index),
+                    &file.content,
+                    if any_text {
+                        &all_tokenizers
+                    } else {
+                        &own_tokenizer
+                    },
+                    config,
+                    excluded,
+                );
+            }
+            if let Some(source) = &project.concatenated_source_code {
+                index.add_document(
+                    project_index,
+                    Document::Concatenated,
+                    source,
+                    &all_tokenizers,
+                    config,
+                    excluded,
+                );
+            }
+            index.files.push(paths);
+        }
+        index
+    }
+
+    fn add_document(
+        &mut self,
+        project: usize,
+        document: Document,
+        content: &str,
+        tokenizers: &[&dyn Tokenizer],
+        config: &ComparisonConfig,
+        excluded: &ExcludedFingerprints,
+    ) {
+        let mut first_positions: FxHashMap<u64, usize> = FxHashMap::default();
+        let mut add = |hash: u64, position: usize, excluded: &FxHashSet<u64>| {
+            if !excluded.contains(&hash) {
+                first_positions
+                    .entry(hash)
+                    .and_modify(|first| *first = (*first).min(position))
+                    .or_insert(position);
+            }
+        };
+
+        let mut modes = vec![(TokenNormalization::Raw, &excluded.moss)];
+        if config.renaming_resistant {
+            modes.push((TokenNormalization::Renaming, &excluded.normalized_moss));
+        }
+        let mut kgramless = false;
+        // Greedy String Tiling only compares files, never concatenated sources.
+        let gst = config.greedy_string_tiling && matches!(document, Document::File(_));
+        for &tokenizer in tokenizers {
+            for &(normalization, excluded) in &modes {
+                let fingerprints = moss_fingerprints(
+                    content,
+                    tokenizer,
+                    normalization,
+                    config.moss_k,
+                    config.moss_window,
+                );
+                kgramless |= fingerprints.is_empty();
+                for (hash, position) in fingerprints {
+                    add(hash, position, excluded);
+                }
+            }
+            if gst {
+                // Every tile spans at least one k-gram of non-excluded tokens.
+                let tokens: Vec<String> = tokenizer
+                    .tokenize(content)
+                    .iter()
+                    .map(|token| {
+                        token
+                            .normalized_text(TokenNormalization::Renaming)
+                            .to_string()
+                    })
+                    .collect();
+                for (hash, position) in
+                    generate_token_kgram_hashes(&tokens, config.gst_min_match_length)
+                {
+                    add(hash, position, &excluded.gst);
+                }
+            }
+        }
+        for (hash, position) in generate_byte_kgrams(content.as_bytes(), config.rabin_karp_k) {
+            add(hash, position, &excluded.rabin_karp);
+        }
+
+        if kgramless {
+            self.kgramless.push((project, document));
+        }
+        for (hash, position) in first_positions {
+            self.postings.entry(hash).or_default().push(Posting {
+                project,
+                document,
+                position,
+            });
+        }
+    }
+
+    /// Every document containing `fingerprint`.
+    pub fn postings(&self, fingerprint: u64) -> &[Posting] {
+        self.postings.get(&fingerprint).map_or(&[], Vec::as_slice)
+    }
+
+    /// Documents worth comparing for each pair of submissions, keyed by the positions of
+    /// both submissions, lowest first. Pairs of submissions missing from the map share no
+    /// fingerprint.
+    pub fn candidate_pairs(&self) -> FxHashMap<(usize, usize), CandidatePairs> {
+        // Postings are appended submission by submission, so every list is sorted by
+        // submission and each pair below has `a.0 <= b.0`.
+        // Boilerplate shared by the whole cohort, such as includes or unregistered starter
+        // code, puts many fingerprints in the same documents: each set of documents is
+        // paired once.
+        let mut shared: FxHashSet<((usize, Document), (usize, Document))> = FxHashSet::default();
+        let documents: FxHashSet<Vec<(usize, Document)>> = self
+            .postings
+            .values()
+            .map(|postings| {
+                postings
+                    .iter()
+                    .map(|posting| (posting.project, posting.document))
+                    .collect()
+            })
+            .chain([self.kgramless.clone()])
+            .collect();
+        for documents in documents {
+            for (i, &a) in documents.iter().enumerate() {
+                for &b in &documents[i + 1..] {
+                    let same_kind = matches!(
+                        (a.1, b.1),
+                        (Document::File(_), Document::File(_))
+                            | (Document::Concatenated, Document::Concatenated)
+                    );
+                    if a.0 != b.0 && same_kind {
+                        shared.insert((a, b));
+                    }
+                }
+            }
+        }
+
+        let mut candidates: FxHashMap<(usize, usize), CandidatePairs> = FxHashMap::default();
+        for ((project_a, document_a), (project_b, document_b)) in shared {
+            let pair = candidates.entry((project_a, project_b)).or_default();
+            match (document_a, document_b) {
+                (Document::File(file_a), Document::File(file_b)) => {
+                    pair.files
+                        .entry(self.files[project_a][file_a].clone())
+                        .or_default()
+                        .insert(self.files[project_b][file_b].clone());
+                }
+                _ => pair.concatenated = true,
+            }
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::MossResult;
+    use crate::comparison_orchestrator::{
+        ProjectComparisonReport, RabinKarpComparisonResult, compare_normalized_projects,
+        compare_normalized_projects_indexed,
+    };
+    use crate::project_processor::ProcessedFile;
+
+    fn project(id: &str, files: &[(&str, &str)]) -> NormalizedProject {
+        let files: FxHashMap<PathBuf, ProcessedFile> = files
+            .iter()
+            .map(|(path, content)| {
+                let path = PathBuf::from(path);
+                let language = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("c") => SourceLanguage::C,
+                    Some("py") => SourceLanguage::Python,
+                    _ => SourceLanguage::Text,
+                };
+                let file = ProcessedFile {
+                    relative_path: path.clone(),
+                    content: content.to_string(),
+                    language,
+                    sha1_hash: "mock".to_string(),
+                    char_length: content.chars().count(),
+                    line_count: content.lines().count(),
+                };
+                (path, file)
+            })
+            .collect();
+        let mut sources: Vec<(&PathBuf, &ProcessedFile)> = files
+            .iter()
+            .filter(|(_, file)| file.language != SourceLanguage::Text)
+            .collect();
+        sources.sort_by(|a, b| a.0.cmp(b.0));
+        let concatenated_source_code = (!sources.is_empty()).then(|| {
+            sources
+                .iter()
+                .map(|(_, file)| file.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n---FILE_SEPARATOR---\n\n")
+        });
+        NormalizedProject {
+            project_id: id.to_string(),
+            files,
+            concatenated_source_code,
+            concatenated_source_hash: None,
+        }
+    }
+
+    const MAX: &str = "int max(int *values, int n) {\n    int best = values[0];\n    while (--n > 0) {\n        if (values[n] > best) best = values[n];\n    }\n    return best;\n}\n";
+
+    fn corpus() -> Vec<NormalizedProject> {
+        let sum = "int sum(int *values, int n) {\n    int total = 0;\n    for (int i = 0; i < n; i++) {\n        total += values[i];\n    }\n    return total;\n}\n";
+        let renamed_sum = "int add_all(int *xs, int count) {\n    int acc = 0;\n    for (int j = 0; j < count; j++) {\n        acc += xs[j];\n    }\n    return acc;\n}\n";
+        let python = "def mean(values):\n    if not values:\n        return 0\n    return sum(values) / len(values)\n";
+        let other_python =
+            "import sys\n\nfor line in sys.stdin:\n    print(line.strip().upper())\n";
+        // Renamed and reindented: only renaming-normalized tokens are left in common.
+        let renamed_other_python =
+            "import os\n\nfor row in os.environ:\n\tprint(row.rstrip().lower())\n";
+        vec![
+            project("A", &[("sum.c", sum), ("stats.py", python)]),
+            project(
+                "B",
+                &[
+                    ("total.c", sum),
+                    ("notes.txt", "shared notes\nabout the\nproject\n"),
+                ],
+            ),
+            project("C", &[("add.c", renamed_sum), ("upper.py", other_python)]),
+            project(
+                "D",
+                &[
+                    ("max.c", MAX),
+                    ("notes.txt", "shared notes\nabout the\nproject\n"),
+                ],
+            ),
+            project("E", &[("mean.py", python)]),
+            project(
+                "F",
+                &[("readme.txt", "nothing in common with anyone at all\n\n\n")],
+            ),
+            project("G", &[("shout.py", renamed_other_python)]),
+        ]
+    }
+
+    /// Scores and containments as they reach the API: a missing result counts as zero.
+    fn scores(report: &ProjectComparisonReport) -> Vec<(PathBuf, PathBuf, Vec<f64>)> {
+        let moss = |result: Option<&MossResult>| {
+            result.map_or([0.0; 3], |r| {
+                [r.score, r.containment_doc1, r.containment_doc2]
+            })
+        };
+        let rabin_karp = |result: Option<&RabinKarpComparisonResult>| {
+            result.map_or([0.0; 3], |r| {
+                [r.similarity_score, r.containment_doc1, r.containment_doc2]
+            })
+        };
+        let mut files: Vec<_> = report
+            .file_to_file_comparisons
+            .iter()
+            .map(|comparison| {
+                let mut scores = Vec::new();
+                scores.extend(moss(comparison.moss_result.as_ref()));
+                scores.extend(moss(comparison.normalized_moss_result.as_ref()));
+                scores.extend(rabin_karp(comparison.rabin_karp_result.as_ref()));
+                scores.push(comparison.gst_result.as_ref().map_or(0.0, |r| r.similarity));
+                (
+                    comparison.file1_path.clone(),
+                    comparison.file2_path.clone(),
+                    scores,
+                )
+            })
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut whole_project = Vec::new();
+        whole_project.extend(moss(report.whole_project_moss_result.as_ref()));
+        whole_project.extend(moss(report.whole_project_normalized_moss_result.as_ref()));
+        whole_project.extend(rabin_karp(report.whole_project_rabin_karp_result.as_ref()));
+        files.push((PathBuf::new(), PathBuf::new(), whole_project));
+        files
+    }
+
+    #[test]
+    fn test_postings_point_to_every_document_containing_a_fingerprint() {
+        let projects = corpus();
+        let config = ComparisonConfig::default();
+        let index = FingerprintIndex::build(&projects, &config, &ExcludedFingerprints::default());
+
+        let fingerprint = moss_fingerprints(
+            &projects[0].files[Path::new("sum.c")].content,
+            tokenizer_for(&SourceLanguage::C),
+            TokenNormalization::Raw,
+            config.moss_k,
+            config.moss_window,
+        )[0]
+        .0;
+        let files: Vec<(usize, Document)> = index
+            .postings(fingerprint)
+            .iter()
+            .filter(|posting| posting.document != Document::Concatenated)
+            .map(|posting| (posting.project, posting.document))
+            .collect();
+        // sum.c sorts after stats.py in A, and total.c after notes.txt in B.
+        assert!(files.contains(&(0, Document::File(1))));
+        assert!(files.contains(&(1, Document::File(1))));
+        assert!(
+            !files
+                .iter()
+                .any(|&(project, _)| project == 4 || project == 5)
+        );
+        assert!(index.postings(0xdead_beef).is_empty());
+    }
+
+    #[test]
+    fn test_only_submissions_sharing_fingerprints_are_candidates() {
+        let projects = corpus();
+        let index = FingerprintIndex::build(
+            &projects,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+        let candidates = index.candidate_pairs();
+
+        let ab = &candidates[&(0, 1)];
+        assert!(ab.contains_files(Path::new("sum.c"), Path::new("total.c")));
+        assert!(!ab.contains_files(Path::new("stats.py"), Path::new("total.c")));
+        assert!(ab.contains_concatenated());
+        assert!(candidates[&(0, 4)].contains_files(Path::new("stats.py"), Path::new("mean.py")));
+        assert!(candidates[&(1, 3)].contains_files(Path::new("notes.txt"), Path::new("notes.txt")));
+        assert!(!candidates.keys().any(|&(a, b)| a == 5 || b == 5));
+    }
+
+    #[test]
+    fn test_gst_kgrams_make_renamed_copies_candidates() {
+        let projects = corpus();
+        let renamed_copy = (2, 6);
+        let candidates = |config: &ComparisonConfig| {
+            FingerprintIndex::build(&projects, config, &ExcludedFingerprints::default())
+                .candidate_pairs()
+        };
+
+        assert!(!candidates(&ComparisonConfig::default()).contains_key(&renamed_copy));
+        let gst = candidates(&ComparisonConfig {
+            greedy_string_tiling: true,
+            ..ComparisonConfig::default()
+        });
+        assert!(gst[&renamed_copy].contains_files(Path::new("upper.py"), Path::new("shout.py")));
+    }
+
+    #[test]
+    fn test_indexed_comparison_matches_exhaustive_comparison_of_a_large_cohort() {
+        // Every submission holds the same solution, as an unregistered starter code would.
+        let projects: Vec<NormalizedProject> = (0..65)
+            .map(|i| project(&i.to_string(), &[("max.c", MAX)]))
+            .collect();
+        assert_indexed_comparison_matches_exhaustive(
+            &projects,
+            &ComparisonConfig::default(),
+            &ExcludedFingerprints::default(),
+        );
+    }
+
+    #[test]
+    fn test_indexed_comparison_matches_exhaustive_comparison() {
+        let projects = corpus();
+        let template = project("T", &[("max.c", MAX)]);
+        let gst = ComparisonConfig {
+            greedy_string_tiling: true,
+            ..ComparisonConfig::default()
+        };
+        let configs = [
+            ComparisonConfig::default(),
+            ComparisonConfig {
+                renaming_resistant: true,
+                min_char_length: 0,
+                min_line_count: 0,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                extension_matching: ExtensionMatching::LanguageFamily,
+                ..ComparisonConfig::default()
+            },
+            ComparisonConfig {
+                extension_matching: ExtensionMatching::AnyText,
+                ..ComparisonConfig::default()
+            },
+            gst.clone(),
+            ComparisonConfig {
+                extension_matching: ExtensionMatching::AnyText,
+                renaming_resistant: true,
+                ..gst
+            },
+        ];
+        for config in configs {
+            for excluded in [
+                ExcludedFingerprints::default(),
+                ExcludedFingerprints::from_project(&template, &config),
+            ] {
+                assert_indexed_comparison_matches_exhaustive(&projects, &config, &excluded);
+            }
+        }
+    }
+
+    fn assert_indexed_comparison_matches_exhaustive(
+        projects: &[NormalizedProject],
+        config: &ComparisonConfig,
+        excluded: &ExcludedFingerprints,
+    ) {
+        let candidates = FingerprintIndex::build(projects, config, excluded).candidate_pairs();
+        let none = CandidatePairs::default();
+        for i in 0..projects.len() {
+            for j in (i + 1)..projects.len() {
+                let exhaustive =
+                    compare_normalized_projects(&projects[i], &projects[j], config, excluded);
+                let indexed = compare_normalized_projects_indexed(
+                    &projects[i],
+                    &projects[j],
+                    config,
+                    excluded,
+                    candidates.get(&(i, j)).unwrap_or(&none),
+                );
+                assert_eq!(
+                    scores(&indexed),
+                    scores(&exhaustive),
+                    "{} vs {}",
+                    projects[i].project_id,
+                    projects[j].project_id
+                );
+            }
+        }
+    }
+}
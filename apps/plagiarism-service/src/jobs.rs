@@ -0,0 +1,1107 @@
+use crate::api::{
+    BodyRequest, ComprehensivePlagiarismResponse, analyze_submissions, cleanup_extracted_folders,
+    download_submissions,
+};
+use crate::comparison_orchestrator::{ComparisonConfig, ExcludedFingerprints};
+use crate::extraction::ExtractionLimits;
+use crate::project_processor::NormalizedProject;
+use crate::storage::SubmissionStore;
+use actix_web::web;
+use apistos::ApiComponent;
+use rustc_hash::FxHashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, broadcast};
+use uuid::Uuid;
+
+/// How long a finished (done or failed) job stays queryable before it is evicted.
+const FINISHED_JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+/// Number of progress events buffered for slow Server-Sent Events subscribers.
+const PROGRESS_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Downloading,
+    Comparing,
+    Done,
+    Failed,
+}
+
+/// Structured progress notification emitted while a check job runs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProgressEvent {
+    StatusChanged {
+        status: JobStatus,
+    },
+    ZipDownloaded {
+        key: String,
+        submission_id: String,
+        downloaded: usize,
+        total: usize,
+    },
+    ProjectNormalized {
+        submission_id: String,
+        file_count: usize,
+    },
+    PairCompared {
+        project_a: String,
+        project_b: String,
+        compared: usize,
+        total: usize,
+    },
+    ReportReady,
+    Failed {
+        error: String,
+    },
+}
+
+impl ProgressEvent {
+    /// Whether no further events will follow this one.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ProgressEvent::ReportReady | ProgressEvent::Failed { .. }
+        )
+    }
+
+    /// Formats the event as a Server-Sent Events frame carrying the JSON payload.
+    pub fn to_sse_frame(&self) -> String {
+        let payload = serde_json::to_string(self).unwrap_or_else(|e| {
+            serde_json::json!({ "type": "failed", "error": e.to_string() }).to_string()
+        });
+        format!("data: {payload}\n\n")
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent)]
+pub struct JobAcceptedResponse {
+    #[serde(rename = "jobId")]
+    pub job_id: String,
+    pub status: JobStatus,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, ApiComponent, Clone)]
+pub struct JobStatusResponse {
+    #[serde(rename = "jobId")]
+    pub job_id: String,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub result: Option<ComprehensivePlagiarismResponse>,
+}
+
+/// Two normalized submissions of a finished job, with the configuration and the excluded
+/// starter code fingerprints it compared them with.
+pub struct SubmissionPair {
+    pub project_a: Arc<NormalizedProject>,
+    pub project_b: Arc<NormalizedProject>,
+    pub config: ComparisonConfig,
+    pub excluded: Arc<ExcludedFingerprints>,
+}
+
+/// Why the submissions of a job could not be looked up.
+#[derive(Debug, PartialEq)]
+pub enum SubmissionLookupError {
+    UnknownJob,
+    NotDone(JobStatus),
+    UnknownSubmission(String),
+}
+
+impl std::fmt::Display for SubmissionLookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SubmissionLookupError::UnknownJob => write!(f, "Unknown plagiarism check job"),
+            SubmissionLookupError::NotDone(status) => {
+                write!(
+                    f,
+                    "Plagiarism check job is not done yet (status: {status:?})"
+                )
+            }
+            SubmissionLookupError::UnknownSubmission(id) => {
+                write!(f, "Submission '{id}' is not part of this plagiarism check")
+            }
+        }
+    }
+}
+
+struct CheckJob {
+    status: JobStatus,
+    error: Option<String>,
+    result: Option<ComprehensivePlagiarismResponse>,
+    finished_at: Option<Instant>,
+    events: Vec<ProgressEvent>,
+    progress: broadcast::Sender<ProgressEvent>,
+    projects: FxHashMap<String, Arc<NormalizedProject>>,
+    config: ComparisonConfig,
+    excluded: Arc<ExcludedFingerprints>,
+}
+
+impl CheckJob {
+    fn new(config: ComparisonConfig) -> Self {
+        let (progress, _) = broadcast::channel(PROGRESS_CHANNEL_CAPACITY);
+        Self {
+            status: JobStatus::Queued,
+            error: None,
+            result: None,
+            finished_at: None,
+            events: Vec::new(),
+            progress,
+            projects: FxHashMap::default(),
+            config,
+            excluded: Arc::default(),
+        }
+    }
+
+    fn record(&mut self, event: ProgressEvent) {
+        // Sending only fails when nobody is listening, which is fine.
+        let _ = self.progress.send(event.clone());
+        self.events.push(event);
+    }
+
+    fn is_expired(&self) -> bool {
+        self.finished_at
+            .is_some_and(|finished_at| finished_at.elapsed() > FINISHED_JOB_RETENTION)
+    }
+}
+
+/// Directory holding the archives extracted for a job, removed with everything in it when
+/// dropped, whichever way the job ends.
+struct JobDirectory(PathBuf);
+
+impl Drop for JobDirectory {
+    fn drop(&mut self) {
+        if self.0.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.0) {
+                eprintln!(
+                    "Failed to cleanup job directory {}: {}",
+                    self.0.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Keeps track of plagiarism check jobs and runs them on a bounded pool of workers.
+///
+/// At most `max_concurrent_jobs` checks download and compare submissions at the same
+/// time; the others wait in the `queued` state. The comparison phase runs on a dedicated
+/// rayon pool of `comparison_threads` threads (one per core when 0), shared by all jobs,
+/// so it never occupies an actix worker. Each job downloads up to
+/// `max_concurrent_downloads` submissions at once, and extracts each of them within
+/// `extraction_limits`.
+pub struct JobManager {
+    jobs: Mutex<FxHashMap<String, CheckJob>>,
+    workers: Arc<Semaphore>,
+    comparison_pool: rayon::ThreadPool,
+    store: Arc<dyn SubmissionStore>,
+    max_concurrent_downloads: usize,
+    extraction_limits: ExtractionLimits,
+    extract_base_path: PathBuf,
+}
+
+impl JobManager {
+    pub fn new(
+        max_concurrent_jobs: usize,
+        comparison_threads: usize,
+        max_concurrent_downloads: usize,
+        extraction_limits: ExtractionLimits,
+        store: Arc<dyn SubmissionStore>,
+        extract_base_path: &Path,
+    ) -> Result<Self, String> {
+        let comparison_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(comparison_threads)
+            .thread_name(|index| format!("comparison-{index}"))
+            .build()
+            .map_err(|e| format!("Failed to start the comparison thread pool: {e}"))?;
+        Ok(Self {
+            jobs: Mutex::new(FxHashMap::default()),
+            workers: Arc::new(Semaphore::new(max_concurrent_jobs.max(1))),
+            comparison_pool,
+            store,
+            max_concurrent_downloads,
+            extraction_limits,
+            extract_base_path: extract_base_path.to_path_buf(),
+        })
+    }
+
+    /// Registers a new job and schedules it. Returns the job id immediately.
+    pub fn submit(self: &Arc<Self>, request: BodyRequest) -> String {
+        let job_id = Uuid::new_v4().to_string();
+        {
+            let mut jobs = self.lock_jobs();
+            jobs.retain(|_, job| !job.is_expired());
+            jobs.insert(job_id.clone(), CheckJob::new(request.config.clone()));
+        }
+
+        let manager = Arc::clone(self);
+        let extract_dir = self.extract_base_path.join(&job_id);
+        let spawned_job_id = job_id.clone();
+        actix_web::rt::spawn(async move {
+            manager.run(&spawned_job_id, request, extract_dir).await;
+        });
+
+        job_id
+    }
+
+    pub fn status(&self, job_id: &str) -> Option<JobStatusResponse> {
+        self.lock_jobs().get(job_id).map(|job| JobStatusResponse {
+            job_id: job_id.to_string(),
+            status: job.status,
+            error: job.error.clone(),
+            result: job.result.clone(),
+        })
+    }
+
+    /// Returns the progress events emitted so far together with a receiver for the
+    /// upcoming ones. No event can be missed between the two.
+    pub fn subscribe(
+        &self,
+        job_id: &str,
+    ) -> Option<(Vec<ProgressEvent>, broadcast::Receiver<ProgressEvent>)> {
+        self.lock_jobs()
+            .get(job_id)
+            .map(|job| (job.events.clone(), job.progress.subscribe()))
+    }
+
+    /// Returns the normalized sources of two submissions of a finished job.
+    pub fn submission_pair(
+        &self,
+        job_id: &str,
+        submission_a: &str,
+        submission_b: &str,
+    ) -> Result<SubmissionPair, SubmissionLookupError> {
+        let jobs = self.lock_jobs();
+        let job = jobs.get(job_id).ok_or(SubmissionLookupError::UnknownJob)?;
+        if job.status != JobStatus::Done {
+            return Err(SubmissionLookupError::NotDone(job.status));
+        }
+        let lookup = |id: &str| {
+            job.projects
+                .get(id)
+                .map(Arc::clone)
+                .ok_or_else(|| SubmissionLookupError::UnknownSubmission(id.to_string()))
+        };
+        Ok(SubmissionPair {
+            project_a: lookup(submission_a)?,
+            project_b: lookup(submission_b)?,
+            config: job.config.clone(),
+            excluded: Arc::clone(&job.excluded),
+        })
+    }
+
+    async fn run(self: Arc<Self>, job_id: &str, request: BodyRequest, extract_dir: PathBuf) {
+        let Ok(_permit) = Arc::clone(&self.workers).acquire_owned().await else {
+            self.fail(job_id, "Worker pool has been shut down".to_string());
+            return;
+        };
+
+        let job_directory = JobDirectory(extract_dir.clone());
+        self.set_status(job_id, JobStatus::Downloading);
+        let report_progress = |event| self.emit(job_id, event);
+        let downloaded = match download_submissions(
+            self.store.as_ref(),
+            &request,
+            &extract_dir,
+            self.max_concurrent_downloads,
+            &self.extraction_limits,
+            &report_progress,
+        )
+        .await
+        {
+            Ok(extracted) => extracted,
+            Err(e) => {
+                eprintln!("Job {job_id} failed while downloading submissions: {e}");
+                drop(job_directory);
+                self.fail(job_id, e);
+                return;
+            }
+        };
+
+        self.set_status(job_id, JobStatus::Comparing);
+        let manager = Arc::clone(&self);
+        let blocking_job_id = job_id.to_string();
+        let outcome = web::block(move || {
+            let report_progress = |event| manager.emit(&blocking_job_id, event);
+            let analysis = manager
+                .comparison_pool
+                .install(|| analyze_submissions(&request, &downloaded, &report_progress));
+            cleanup_extracted_folders(&downloaded.submissions);
+            analysis
+        })
+        .await;
+
+        drop(job_directory);
+
+        match outcome {
+            Ok(Ok(analysis)) => self.update(job_id, |job| {
+                job.status = JobStatus::Done;
+                job.result = Some(analysis.response);
+                job.projects = analysis
+                    .projects
+                    .into_iter()
+                    .map(|project| (project.project_id.clone(), Arc::new(project)))
+                    .collect();
+                job.excluded = Arc::new(analysis.excluded);
+                job.finished_at = Some(Instant::now());
+                job.record(ProgressEvent::StatusChanged {
+                    status: JobStatus::Done,
+                });
+                job.record(ProgressEvent::ReportReady);
+            }),
+            Ok(Err(e)) => {
+                eprintln!("Job {job_id} failed while comparing submissions: {e}");
+                self.fail(job_id, e);
+            }
+            Err(e) => {
+                eprintln!("Job {job_id} failed while comparing submissions: {e}");
+                self.fail(job_id, format!("Comparison worker failed: {e}"));
+            }
+        }
+    }
+
+    fn set_status(&self, job_id: &str, status: JobStatus) {
+        self.update(job_id, |job| {
+            job.status = status;
+            job.record(ProgressEvent::StatusChanged { status });
+        });
+    }
+
+    fn emit(&self, job_id: &str, event: ProgressEvent) {
+        self.update(job_id, |job| job.record(event));
+    }
+
+    fn fail(&self, job_id: &str, error: String) {
+        self.update(job_id, |job| {
+            job.status = JobStatus::Failed;
+            job.error = Some(error.clone());
+            job.finished_at = Some(Instant::now());
+            job.record(ProgressEvent::StatusChanged {
+                status: JobStatus::Failed,
+            });
+            job.record(ProgressEvent::Failed { error });
+        });
+    }
+
+    fn update(&self, job_id: &str, apply: impl FnOnce(&mut CheckJob)) {
+        if let Some(job) = self.lock_jobs().get_mut(job_id) {
+            apply(job);
+        }
+    }
+
+    fn lock_jobs(&self) -> MutexGuard<'_, FxHashMap<String, CheckJob>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{ApiCluster, SkipStage};
+    use crate::storage::{ObjectMetadata, StoreError};
+    use async_trait::async_trait;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use zip::write::SimpleFileOptions;
+
+    struct InMemoryStore {
+        objects: Mutex<FxHashMap<String, Vec<u8>>>,
+        /// Errors returned by the next downloads of each key, before its content.
+        failures: Mutex<FxHashMap<String, Vec<StoreError>>>,
+        downloads_in_flight: AtomicUsize,
+        max_downloads_in_flight: AtomicUsize,
+    }
+
+    impl InMemoryStore {
+        fn new(objects: FxHashMap<String, Vec<u8>>) -> Self {
+            Self {
+                objects: Mutex::new(objects),
+                failures: Mutex::default(),
+                downloads_in_flight: AtomicUsize::new(0),
+                max_downloads_in_flight: AtomicUsize::new(0),
+            }
+        }
+
+        fn objects(&self) -> MutexGuard<'_, FxHashMap<String, Vec<u8>>> {
+            self.objects.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        fn fail_next_downloads(&self, key: &str, mut errors: Vec<StoreError>) {
+            errors.reverse();
+            self.failures
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key.to_string(), errors);
+        }
+    }
+
+    #[async_trait]
+    impl SubmissionStore for InMemoryStore {
+        async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, String> {
+            let mut keys: Vec<String> = self
+                .objects()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect();
+            keys.sort();
+            Ok(keys)
+        }
+
+        async fn get_object(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+            let in_flight = self.downloads_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_downloads_in_flight
+                .fetch_max(in_flight, Ordering::SeqCst);
+            actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+            self.downloads_in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let failure = self
+                .failures
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_mut(key)
+                .and_then(Vec::pop);
+            if let Some(error) = failure {
+                return Err(error);
+            }
+            self.objects()
+                .get(key)
+                .cloned()
+                .ok_or_else(|| StoreError::Permanent(format!("No such key: {key}")))
+        }
+
+        async fn put_object(&self, key: &str, content: Vec<u8>) -> Result<(), String> {
+            self.objects().insert(key.to_string(), content);
+            Ok(())
+        }
+
+        async fn head_object(&self, key: &str) -> Result<Option<ObjectMetadata>, String> {
+            Ok(self.objects().get(key).map(|content| ObjectMetadata {
+                size_bytes: content.len() as u64,
+            }))
+        }
+    }
+
+    fn zip_submission(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn request() -> BodyRequest {
+        BodyRequest {
+            project_id: "1".to_string(),
+            promotion_id: "2".to_string(),
+            step: "3".to_string(),
+            config: ComparisonConfig::default(),
+            archived_promotion_ids: Vec::new(),
+        }
+    }
+
+    /// Job manager over an in-memory store holding the `(submissionId, archive)` pairs in
+    /// the step checked by `request()`. At most two downloads run at once.
+    fn manager_with_submissions(
+        submissions: &[(&str, Vec<u8>)],
+        limits: ExtractionLimits,
+        extract_base_path: &Path,
+    ) -> (Arc<JobManager>, Arc<InMemoryStore>) {
+        let objects = submissions
+            .iter()
+            .map(|(submission, archive)| {
+                (
+                    format!("project-1/promo-2/step-3/{submission}.zip"),
+                    archive.clone(),
+                )
+            })
+            .collect();
+        let store = Arc::new(InMemoryStore::new(objects));
+        let manager = JobManager::new(1, 2, 2, limits, store.clone(), extract_base_path).unwrap();
+        (Arc::new(manager), store)
+    }
+
+    fn manager_with_corpus(extract_base_path: &Path) -> Arc<JobManager> {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let other = "for i in range(10):\n    if i % 2 == 0:\n        print(i)\n";
+        let submissions = [("10-1", solution), ("11-1", solution), ("12-1", other)]
+            .map(|(submission, content)| (submission, zip_submission(&[("main.py", content)])));
+        manager_with_submissions(&submissions, ExtractionLimits::default(), extract_base_path).0
+    }
+
+    #[test]
+    fn test_unknown_job_has_no_status() {
+        let manager = manager_with_corpus(Path::new("unused"));
+        assert!(manager.status("does-not-exist").is_none());
+        assert!(manager.subscribe("does-not-exist").is_none());
+    }
+
+    #[test]
+    fn test_failed_job_reports_error() {
+        let manager = manager_with_corpus(Path::new("unused"));
+        manager.lock_jobs().insert(
+            "job".to_string(),
+            CheckJob::new(ComparisonConfig::default()),
+        );
+
+        manager.set_status("job", JobStatus::Downloading);
+        assert_eq!(
+            manager.submission_pair("job", "a", "b").err(),
+            Some(SubmissionLookupError::NotDone(JobStatus::Downloading))
+        );
+        assert_eq!(
+            manager.status("job").map(|job| job.status),
+            Some(JobStatus::Downloading)
+        );
+
+        manager.fail("job", "bucket unreachable".to_string());
+        let job = manager.status("job").unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error.as_deref(), Some("bucket unreachable"));
+        assert!(job.result.is_none());
+
+        let (history, _) = manager.subscribe("job").unwrap();
+        assert_eq!(
+            history.last(),
+            Some(&ProgressEvent::Failed {
+                error: "bucket unreachable".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_only_finished_jobs_expire() {
+        let mut job = CheckJob::new(ComparisonConfig::default());
+        assert!(!job.is_expired());
+
+        job.finished_at = Instant::now().checked_sub(FINISHED_JOB_RETENTION * 2);
+        assert!(job.is_expired());
+    }
+
+    #[test]
+    fn test_progress_event_sse_frame() {
+        let event = ProgressEvent::ProjectNormalized {
+            submission_id: "10-1".to_string(),
+            file_count: 3,
+        };
+        assert_eq!(
+            event.to_sse_frame(),
+            "data: {\"type\":\"projectNormalized\",\"submissionId\":\"10-1\",\"fileCount\":3}\n\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_job_streams_progress_until_report_is_ready() {
+        let extract_dir = tempfile::tempdir().unwrap();
+        let manager = manager_with_corpus(extract_dir.path());
+
+        let job_id = manager.submit(request());
+        let (history, mut receiver) = manager.subscribe(&job_id).unwrap();
+        let mut events = history;
+        while !events.last().is_some_and(ProgressEvent::is_terminal) {
+            events.push(receiver.recv().await.unwrap());
+        }
+
+        let downloaded = events
+            .iter()
+            .filter(|event| matches!(event, ProgressEvent::ZipDownloaded { total: 3, .. }))
+            .count();
+        assert_eq!(downloaded, 3);
+        assert!(events.contains(&ProgressEvent::ProjectNormalized {
+            submission_id: "11-1".to_string(),
+            file_count: 1,
+        }));
+        let pairs: Vec<usize> = events
+            .iter()
+            .filter_map(|event| match event {
+                ProgressEvent::PairCompared {
+                    compared, total: 3, ..
+                } => Some(*compared),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pairs, vec![1, 2, 3]);
+        assert_eq!(events.last(), Some(&ProgressEvent::ReportReady));
+
+        let job = manager.status(&job_id).unwrap();
+        assert_eq!(job.status, JobStatus::Done);
+        let report = job.result.unwrap();
+        assert_eq!(report.folder_results.len(), 3);
+        assert!(!extract_dir.path().join(&job_id).exists());
+
+        let pair = manager.submission_pair(&job_id, "10-1", "12-1").unwrap();
+        assert_eq!(pair.project_a.project_id, "10-1");
+        assert_eq!(pair.project_b.project_id, "12-1");
+        assert_eq!(pair.config, ComparisonConfig::default());
+        assert_eq!(
+            manager.submission_pair(&job_id, "10-1", "99-1").err(),
+            Some(SubmissionLookupError::UnknownSubmission("99-1".to_string()))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_events_endpoint_replays_finished_job_as_sse() {
+        use crate::api::{AppState, stream_check_events};
+        use actix_web::{App, test};
+        use apistos::app::OpenApiWrapper;
+        use apistos::spec::Spec;
+        use apistos::web::{get, resource};
+
+        let extract_dir = tempfile::tempdir().unwrap();
+        let manager = manager_with_corpus(extract_dir.path());
+        let job_id = manager.submit(request());
+        let (_, mut receiver) = manager.subscribe(&job_id).unwrap();
+        while !receiver.recv().await.unwrap().is_terminal() {}
+
+        let app = test::init_service(
+            App::new()
+                .document(Spec::default())
+                .app_data(actix_web::web::Data::new(AppState {
+                    jobs: Arc::clone(&manager),
+                    store: Arc::new(InMemoryStore::new(FxHashMap::default())),
+                }))
+                .service(resource("/checks/{jobId}/events").route(get().to(stream_check_events)))
+                .build("/docs"),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/checks/{job_id}/events"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(
+            body.starts_with("data: {\"type\":\"statusChanged\",\"status\":\"downloading\"}\n\n")
+        );
+        assert!(body.ends_with("data: {\"type\":\"reportReady\"}\n\n"));
+
+        let unknown = test::TestRequest::get()
+            .uri("/checks/unknown/events")
+            .to_request();
+        assert_eq!(test::call_service(&app, unknown).await.status(), 404);
+    }
+
+    async fn run_to_completion(manager: &Arc<JobManager>) -> JobStatusResponse {
+        let job_id = manager.submit(request());
+        let (_, mut receiver) = manager.subscribe(&job_id).unwrap();
+        while !receiver.recv().await.unwrap().is_terminal() {}
+        manager.status(&job_id).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_report_does_not_depend_on_comparison_threads() {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let renamed = "def plus(x, y):\n    return x + y\n\nprint(plus(1, 2))\n";
+        let helper = "def double(n):\n    return add(n, n)\n\nprint(double(21))\n";
+        let mut objects = FxHashMap::default();
+        for (submission, files) in [
+            ("10-1", vec![("main.py", solution), ("helper.py", helper)]),
+            ("11-1", vec![("main.py", solution)]),
+            ("12-1", vec![("main.py", renamed), ("helper.py", helper)]),
+            ("13-1", vec![("app.py", solution), ("util.py", renamed)]),
+            ("14-1", vec![("main.py", helper)]),
+        ] {
+            objects.insert(
+                format!("project-1/promo-2/step-3/{submission}.zip"),
+                zip_submission(&files),
+            );
+        }
+        let store = Arc::new(InMemoryStore::new(objects));
+
+        let mut reports = Vec::new();
+        for threads in [1, 4] {
+            let extract_dir = tempfile::tempdir().unwrap();
+            let manager = Arc::new(
+                JobManager::new(
+                    1,
+                    threads,
+                    4,
+                    ExtractionLimits::default(),
+                    store.clone(),
+                    extract_dir.path(),
+                )
+                .unwrap(),
+            );
+            let report = run_to_completion(&manager).await.result.unwrap();
+            reports.push(serde_json::to_string(&report).unwrap());
+        }
+        assert_eq!(reports[0], reports[1]);
+    }
+
+    #[actix_web::test]
+    async fn test_job_excludes_registered_starter_code() {
+        let starter = "import sys\n\ndef read_numbers():\n    return [int(line) for line in sys.stdin if line.strip()]\n\nif __name__ == \"__main__\":\n    numbers = read_numbers()\n";
+        let submissions = ["10-1", "11-1"]
+            .map(|submission| (submission, zip_submission(&[("main.py", starter)])));
+        let extract_dir = tempfile::tempdir().unwrap();
+        let (manager, store) = manager_with_submissions(
+            &submissions,
+            ExtractionLimits::default(),
+            extract_dir.path(),
+        );
+
+        let report = run_to_completion(&manager).await.result.unwrap();
+        assert!(!report.template_excluded);
+        assert!(report.folder_results[0].plagiarism_percentage > 90.0);
+
+        store.objects().insert(
+            "project-1/promo-2/step-3/base-code.zip".to_string(),
+            zip_submission(&[("main.py", starter)]),
+        );
+        let job = run_to_completion(&manager).await;
+        let report = job.result.unwrap();
+        assert!(report.template_excluded);
+        let submissions: Vec<&str> = report
+            .folder_results
+            .iter()
+            .map(|folder| folder.folder_name.as_str())
+            .collect();
+        assert_eq!(submissions, vec!["10-1", "11-1"]);
+        for folder in &report.folder_results {
+            assert_eq!(folder.plagiarism_percentage, 0.0);
+            assert!(folder.matches[0].flags.is_empty());
+            assert!(folder.matches[0].file_comparisons.is_empty());
+        }
+
+        let pair = manager
+            .submission_pair(&job.job_id, "10-1", "11-1")
+            .unwrap();
+        assert!(!pair.excluded.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_report_clusters_submissions_sharing_a_solution() {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let other = "for i in range(10):\n    if i % 2 == 0:\n        print(i)\n";
+        let submissions = [
+            ("10-1747262120000", solution),
+            ("11-1747261500000", solution),
+            ("12-1747261920000", solution),
+            ("13-1747261260000", other),
+        ]
+        .map(|(submission, content)| (submission, zip_submission(&[("main.py", content)])));
+        let extract_dir = tempfile::tempdir().unwrap();
+        let (manager, _) = manager_with_submissions(
+            &submissions,
+            ExtractionLimits::default(),
+            extract_dir.path(),
+        );
+
+        let report = run_to_completion(&manager).await.result.unwrap();
+        assert_eq!(
+            report.clusters,
+            vec![ApiCluster {
+                members: vec![
+                    "10-1747262120000".to_string(),
+                    "11-1747261500000".to_string(),
+                    "12-1747261920000".to_string(),
+                ],
+                average_score: 100.0,
+                min_score: 100.0,
+                likely_origin: Some("11-1747261500000".to_string()),
+            }]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_job_compares_archived_submissions_with_checked_ones_only() {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let other = "for i in range(10):\n    if i % 2 == 0:\n        print(i)\n";
+        let submissions = [("10-1", solution), ("11-1", other)]
+            .map(|(submission, content)| (submission, zip_submission(&[("main.py", content)])));
+        let extract_dir = tempfile::tempdir().unwrap();
+        let (manager, store) = manager_with_submissions(
+            &submissions,
+            ExtractionLimits::default(),
+            extract_dir.path(),
+        );
+        for (key, content) in [
+            ("promo-1/step-3/20-1", solution),
+            ("promo-1/step-3/21-1", solution),
+            ("promo-4/step-3/10-1", other),
+        ] {
+            store.objects().insert(
+                format!("project-1/{key}.zip"),
+                zip_submission(&[("main.py", content)]),
+            );
+        }
+
+        let job_id = manager.submit(BodyRequest {
+            archived_promotion_ids: vec!["1".to_string()],
+            ..request()
+        });
+        let (_, mut receiver) = manager.subscribe(&job_id).unwrap();
+        let mut compared_pairs = Vec::new();
+        loop {
+            let event = receiver.recv().await.unwrap();
+            if let ProgressEvent::PairCompared {
+                project_a,
+                project_b,
+                ..
+            } = &event
+            {
+                compared_pairs.push((project_a.clone(), project_b.clone()));
+            }
+            if event.is_terminal() {
+                break;
+            }
+        }
+        // The two archived submissions are never compared with each other.
+        assert_eq!(compared_pairs.len(), 5);
+        assert!(
+            !compared_pairs
+                .iter()
+                .any(|(a, b)| a.starts_with("promo-1-") && b.starts_with("promo-1-"))
+        );
+
+        let report = manager.status(&job_id).unwrap().result.unwrap();
+        let folders: Vec<&str> = report
+            .folder_results
+            .iter()
+            .map(|folder| folder.folder_name.as_str())
+            .collect();
+        assert_eq!(folders, vec!["10-1", "11-1"]);
+        let copied = &report.folder_results[0];
+        assert!(copied.plagiarism_percentage > 90.0);
+        for archived in ["promo-1-20-1", "promo-1-21-1"] {
+            let detail = copied
+                .matches
+                .iter()
+                .find(|detail| detail.matched_folder == archived)
+                .unwrap();
+            assert_eq!(detail.archived_promotion_id.as_deref(), Some("1"));
+        }
+        let current = copied
+            .matches
+            .iter()
+            .find(|detail| detail.matched_folder == "11-1")
+            .unwrap();
+        assert_eq!(current.archived_promotion_id, None);
+    }
+
+    #[actix_web::test]
+    async fn test_job_retries_transient_download_errors_and_reports_skipped_submissions() {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let mut submissions: Vec<(&str, Vec<u8>)> =
+            ["10-1", "11-1", "12-1", "13-1", "14-1", "15-1"]
+                .into_iter()
+                .map(|submission| (submission, zip_submission(&[("main.py", solution)])))
+                .collect();
+        submissions.push(("16-1", b"not a zip".to_vec()));
+        // A `.gitignore` folder cannot be read as the list of ignored files.
+        submissions.push(("17-1", zip_submission(&[(".gitignore/main.py", solution)])));
+        let extract_dir = tempfile::tempdir().unwrap();
+        let (manager, store) = manager_with_submissions(
+            &submissions,
+            ExtractionLimits::default(),
+            extract_dir.path(),
+        );
+        store.fail_next_downloads(
+            "project-1/promo-2/step-3/11-1.zip",
+            vec![
+                StoreError::Transient("timed out".to_string()),
+                StoreError::Transient("503".to_string()),
+            ],
+        );
+        store.fail_next_downloads(
+            "project-1/promo-2/step-3/12-1.zip",
+            vec![StoreError::Permanent("access denied".to_string())],
+        );
+        store.fail_next_downloads(
+            "project-1/promo-2/step-3/13-1.zip",
+            vec![StoreError::Transient("timed out".to_string()); 4],
+        );
+
+        let report = run_to_completion(&manager).await.result.unwrap();
+        let folders: Vec<&str> = report
+            .folder_results
+            .iter()
+            .map(|folder| folder.folder_name.as_str())
+            .collect();
+        assert_eq!(folders, vec!["10-1", "11-1", "14-1", "15-1"]);
+        let skipped: Vec<(&str, SkipStage, u32)> = report
+            .skipped_submissions
+            .iter()
+            .map(|skipped| {
+                (
+                    skipped.submission_id.as_str(),
+                    skipped.stage,
+                    skipped.attempts,
+                )
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("12-1", SkipStage::Download, 1),
+                ("13-1", SkipStage::Download, 4),
+                ("16-1", SkipStage::OpenArchive, 1),
+                ("17-1", SkipStage::Processing, 1),
+            ]
+        );
+        assert!(
+            report.skipped_submissions[0]
+                .error
+                .ends_with("access denied")
+        );
+        assert_eq!(
+            report.skipped_submissions[0].key,
+            "project-1/promo-2/step-3/12-1.zip"
+        );
+        assert!(
+            report.skipped_submissions[2]
+                .error
+                .contains("Failed to read zip archive")
+        );
+        assert_eq!(
+            report.skipped_submissions[3].key,
+            "project-1/promo-2/step-3/17-1.zip"
+        );
+        assert!(report.skipped_submissions[3].error.contains(".gitignore"));
+        assert_eq!(store.max_downloads_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_job_skips_submissions_breaking_extraction_limits() {
+        let solution = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+        let big = "x".repeat(2048);
+        let submissions = [
+            ("10-1", zip_submission(&[("main.py", solution)])),
+            ("11-1", zip_submission(&[("main.py", solution)])),
+            ("12-1", zip_submission(&[("../../escape.py", solution)])),
+            (
+                "13-1",
+                zip_submission(&[("main.py", solution), ("big.py", &big)]),
+            ),
+        ];
+        let extract_dir = tempfile::tempdir().unwrap();
+        let limits = ExtractionLimits {
+            max_file_bytes: 1024,
+            ..ExtractionLimits::default()
+        };
+        let (manager, _) =
+            manager_with_submissions(&submissions, limits, &extract_dir.path().join("jobs"));
+
+        let report = run_to_completion(&manager).await.result.unwrap();
+        let folders: Vec<&str> = report
+            .folder_results
+            .iter()
+            .map(|folder| folder.folder_name.as_str())
+            .collect();
+        assert_eq!(folders, vec!["10-1", "11-1"]);
+        let skipped: Vec<(&str, SkipStage)> = report
+            .skipped_submissions
+            .iter()
+            .map(|skipped| (skipped.submission_id.as_str(), skipped.stage))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("12-1", SkipStage::Extraction),
+                ("13-1", SkipStage::Extraction)
+            ]
+        );
+        assert!(
+            report.skipped_submissions[0]
+                .error
+                .contains("outside of the extraction directory")
+        );
+        assert!(
+            report.skipped_submissions[1]
+                .error
+                .contains("'big.py' extracts to more than 1024 bytes")
+        );
+        assert!(!extract_dir.path().join("escape.py").exists());
+    }
+
+    #[actix_web::test]
+    async fn test_job_fails_on_unusable_starter_code() {
+        // A `.gitignore` folder cannot be read as the list of ignored files.
+        let unprocessable = zip_submission(&[(".gitignore/main.py", "print(1)\n")]);
+        for (template, error) in [
+            (b"not a zip".to_vec(), "base-code.zip"),
+            (unprocessable, "Unusable starter code: Failed to read"),
+        ] {
+            let submissions = [
+                ("10-1", zip_submission(&[("main.py", "print(1)\n")])),
+                ("base-code", template),
+            ];
+            let extract_dir = tempfile::tempdir().unwrap();
+            let (manager, _) = manager_with_submissions(
+                &submissions,
+                ExtractionLimits::default(),
+                extract_dir.path(),
+            );
+
+            let job = run_to_completion(&manager).await;
+            assert_eq!(job.status, JobStatus::Failed);
+            assert!(job.error.unwrap().contains(error), "{error}");
+            assert!(!extract_dir.path().join(&job.job_id).exists());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_register_template_endpoint_stores_zip() {
+        use crate::api::{AppState, register_template};
+        use actix_web::{App, test};
+        use apistos::app::OpenApiWrapper;
+        use apistos::spec::Spec;
+        use apistos::web::{put, resource};
+
+        let (jobs, store) =
+            manager_with_submissions(&[], ExtractionLimits::default(), Path::new("unused"));
+        let app = test::init_service(
+            App::new()
+                .document(Spec::default())
+                .app_data(actix_web::web::Data::new(AppState {
+                    jobs,
+                    store: store.clone(),
+                }))
+                .service(
+                    resource("/templates/{projectId}/{promotionId}/{step}")
+                        .route(put().to(register_template)),
+                )
+                .build("/docs"),
+        )
+        .await;
+
+        let archive = zip_submission(&[("main.py", "print(1)\n")]);
+        let request = test::TestRequest::put()
+            .uri("/templates/1/2/3")
+            .set_payload(archive.clone())
+            .to_request();
+        let response: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(response["key"], "project-1/promo-2/step-3/base-code.zip");
+        assert_eq!(response["sizeBytes"], archive.len());
+        assert_eq!(
+            store
+                .objects()
+                .get("project-1/promo-2/step-3/base-code.zip"),
+            Some(&archive)
+        );
+
+        let invalid = test::TestRequest::put()
+            .uri("/templates/1/2/3")
+            .set_payload("not a zip")
+            .to_request();
+        assert_eq!(test::call_service(&app, invalid).await.status(), 400);
+    }
+}
@@ -1,5 +1,11 @@
 pub mod algorithm;
 pub mod api;
+pub mod clustering;
 pub mod comparison_orchestrator;
+pub mod extraction;
+pub mod fingerprint_index;
+pub mod jobs;
 pub mod project_processor;
 pub mod s3;
+pub mod storage;
+pub mod tokenizer;
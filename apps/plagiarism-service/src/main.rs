@@ -1,21 +1,24 @@
 use actix_web::middleware::Logger;
 use actix_web::{App, HttpServer};
-use api::{AppState, checks_projects};
 use apistos::SwaggerUIConfig;
 use apistos::app::{BuildConfig, OpenApiWrapper};
 use apistos::info::Info;
 use apistos::server::Server;
 use apistos::spec::Spec;
-use apistos::web::{post, resource, scope};
+use plagiarism_service::api::{AppState, routes};
+use plagiarism_service::extraction::ExtractionLimits;
+use plagiarism_service::jobs::JobManager;
+use plagiarism_service::storage::store_from_env;
+use std::env;
 use std::error::Error;
 use std::net::Ipv4Addr;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-mod algorithm;
-mod api;
-mod comparison_orchestrator;
-mod project_processor;
-mod s3;
+const DEFAULT_MAX_CONCURRENT_CHECKS: usize = 2;
+/// Zero lets the comparison pool use one thread per core.
+const DEFAULT_COMPARISON_THREADS: usize = 0;
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 8;
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -33,10 +36,61 @@ async fn main() -> Result<(), Box<dyn Error>> {
         ..Default::default()
     };
 
+    let max_concurrent_checks = env::var("MAX_CONCURRENT_CHECKS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_CHECKS);
+    let comparison_threads = env::var("COMPARISON_THREADS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_COMPARISON_THREADS);
+    let max_concurrent_downloads = env::var("MAX_CONCURRENT_DOWNLOADS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_DOWNLOADS);
+    let extraction_limits = ExtractionLimits::from_env();
     let extract_path = PathBuf::from("./extract");
+    let store = match store_from_env().await {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Failed to set up the submission store: {e}");
+            std::process::exit(1);
+        }
+    };
+    let jobs = Arc::new(JobManager::new(
+        max_concurrent_checks,
+        comparison_threads,
+        max_concurrent_downloads,
+        extraction_limits,
+        Arc::clone(&store),
+        &extract_path,
+    )?);
+
     println!("Starting plagiarism service on http://localhost:3008");
+    println!("Running at most {max_concurrent_checks} plagiarism checks concurrently");
+    if comparison_threads == 0 {
+        println!("Comparing submissions on one thread per core");
+    } else {
+        println!("Comparing submissions on {comparison_threads} threads");
+    }
+    println!("Downloading at most {max_concurrent_downloads} submissions per check concurrently");
+    println!(
+        "Extracting at most {} entries, {} bytes per submission and {} bytes per file, compressed at most {} times",
+        extraction_limits.max_entries,
+        extraction_limits.max_total_bytes,
+        extraction_limits.max_file_bytes,
+        extraction_limits.max_compression_ratio
+    );
     println!("Available endpoints:");
-    println!("  POST /plagiarism/checks - Run plagiarism check");
+    println!("  POST /plagiarism/checks - Queue a plagiarism check");
+    println!("  GET /plagiarism/checks/{{jobId}} - Plagiarism check status and report");
+    println!("  GET /plagiarism/checks/{{jobId}}/events - Plagiarism check progress (SSE)");
+    println!(
+        "  GET /plagiarism/checks/{{jobId}}/report/{{submissionA}}/{{submissionB}} - Side-by-side HTML report"
+    );
+    println!(
+        "  PUT /plagiarism/templates/{{projectId}}/{{promotionId}}/{{step}} - Register starter code (zip body)"
+    );
     println!("  GET /docs - OpenAPI specification (JSON)");
     println!("  GET /ui - Swagger UI");
 
@@ -45,13 +99,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .document(spec.clone())
             .wrap(Logger::default())
             .app_data(actix_web::web::Data::new(AppState {
-                extract_base_path: extract_path.clone(),
+                jobs: Arc::clone(&jobs),
+                store: Arc::clone(&store),
             }))
-            .service(
-                scope("/plagiarism").service(
-                    scope("/checks").service(resource("").route(post().to(checks_projects))),
-                ),
-            )
+            .service(routes())
             .build_with(
                 "/docs",
                 BuildConfig::default().with(SwaggerUIConfig::new(&"/ui")),
@@ -1,14 +1,17 @@
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum SourceLanguage {
     Rust,
     Python,
+    C,
     Text,
     Unknown,
 }
@@ -31,21 +34,37 @@ pub struct NormalizedProject {
     pub concatenated_source_hash: Option<String>,
 }
 
+/// Why a submission folder could not be processed.
 #[derive(Debug)]
 pub enum ProjectProcessorError {
-    IoError(()),
-    WalkDirError(()),
+    /// Reading `path` failed.
+    IoError { path: PathBuf, source: io::Error },
+    /// Walking the submission folder failed at `path`.
+    WalkDirError {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
 }
 
-impl From<io::Error> for ProjectProcessorError {
-    fn from(_err: io::Error) -> Self {
-        ProjectProcessorError::IoError(())
+impl fmt::Display for ProjectProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectProcessorError::IoError { path, source } => {
+                write!(f, "Failed to read {}: {}", path.display(), source)
+            }
+            ProjectProcessorError::WalkDirError { path, source } => {
+                write!(f, "Failed to walk {}: {}", path.display(), source)
+            }
+        }
     }
 }
 
-impl From<walkdir::Error> for ProjectProcessorError {
-    fn from(_err: walkdir::Error) -> Self {
-        ProjectProcessorError::WalkDirError(())
+impl Error for ProjectProcessorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProjectProcessorError::IoError { source, .. } => Some(source),
+            ProjectProcessorError::WalkDirError { source, .. } => Some(source),
+        }
     }
 }
 
@@ -60,11 +79,32 @@ fn detect_language(path: &Path) -> SourceLanguage {
         Some("rs") => SourceLanguage::Rust,
         Some("py") => SourceLanguage::Python,
         Some("txt") => SourceLanguage::Text,
-        Some("c") => SourceLanguage::Text,
+        Some("c" | "h") => SourceLanguage::C,
         _ => SourceLanguage::Unknown,
     }
 }
 
+/// Extensions of files written in the same language, between which code is commonly
+/// moved or renamed. All extensions of a family map to the same `SourceLanguage`.
+const LANGUAGE_FAMILIES: [&[&str]; 3] = [
+    &["c", "h"],
+    &["cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+    &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+];
+
+/// Whether both files have the same extension, or extensions of the same language family.
+pub fn same_language_family(path_a: &Path, path_b: &Path) -> bool {
+    let (extension_a, extension_b) = (path_a.extension(), path_b.extension());
+    extension_a == extension_b
+        || LANGUAGE_FAMILIES.iter().any(|family| {
+            [extension_a, extension_b].iter().all(|extension| {
+                extension
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| family.contains(&ext))
+            })
+        })
+}
+
 pub fn build_blacklist(project_path: &Path) -> io::Result<FxHashSet<String>> {
     let mut blacklist: FxHashSet<String> = FxHashSet::from_iter([
         String::from("target/"),
@@ -117,10 +157,17 @@ pub fn process_project_folder(
     let mut files_map = FxHashMap::default();
     let mut source_files_for_concatenation: Vec<(PathBuf, String)> = Vec::new();
 
-    let blacklist = build_blacklist(project_path)?;
+    let blacklist =
+        build_blacklist(project_path).map_err(|source| ProjectProcessorError::IoError {
+            path: project_path.join(".gitignore"),
+            source,
+        })?;
 
     for entry in walkdir::WalkDir::new(project_path).into_iter() {
-        let entry = entry?;
+        let entry = entry.map_err(|source| ProjectProcessorError::WalkDirError {
+            path: source.path().unwrap_or(project_path).to_path_buf(),
+            source,
+        })?;
         let path = entry.path();
         let path_str = path.to_string_lossy();
 
@@ -167,15 +214,10 @@ pub fn process_project_folder(
                     files_map.insert(relative_path.clone(), processed_file);
 
                     match language {
-                        SourceLanguage::Rust | SourceLanguage::Python => {
+                        SourceLanguage::Rust | SourceLanguage::Python | SourceLanguage::C => {
                             source_files_for_concatenation.push((relative_path, content));
                         }
-                        SourceLanguage::Text => {
-                            if path.extension().and_then(|ext| ext.to_str()) == Some("c") {
-                                source_files_for_concatenation.push((relative_path, content));
-                            }
-                        }
-                        _ => {}
+                        SourceLanguage::Text | SourceLanguage::Unknown => {}
                     }
                 }
                 Err(e) => {
@@ -224,6 +266,32 @@ mod tests {
         (dir, project_path)
     }
 
+    #[test]
+    fn test_same_language_family() {
+        let same = |a: &str, b: &str| same_language_family(Path::new(a), Path::new(b));
+        assert!(same("src/main.c", "include/main.c"));
+        assert!(same("list.c", "list.h"));
+        assert!(same("vector.hpp", "vector.cc"));
+        assert!(same("app.jsx", "app.ts"));
+        assert!(same("Makefile", "LICENSE"));
+        assert!(!same("list.c", "list.cpp"));
+        assert!(!same("main.py", "main.rs"));
+        assert!(!same("notes.txt", "Makefile"));
+    }
+
+    #[test]
+    fn test_language_families_share_a_tokenizer() {
+        for family in LANGUAGE_FAMILIES {
+            let language = detect_language(Path::new(&format!("file.{}", family[0])));
+            for extension in family {
+                assert_eq!(
+                    detect_language(Path::new(&format!("file.{extension}"))),
+                    language
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_process_project_folder_populates_file_lengths() {
         let (_temp_dir, project_path) = setup_test_project("project_with_lengths");
@@ -270,4 +338,25 @@ mod tests {
             "Line count for empty.py should be 0"
         );
     }
+
+    #[test]
+    fn test_process_project_folder_reports_failing_path() {
+        let (_temp_dir, project_path) = setup_test_project("project_with_bad_gitignore");
+        fs::create_dir_all(project_path.join(".gitignore")).unwrap();
+        let missing_path = project_path.join("missing");
+
+        let error = process_project_folder(&project_path, "bad_gitignore").unwrap_err();
+        assert!(matches!(
+            &error,
+            ProjectProcessorError::IoError { path, .. } if *path == project_path.join(".gitignore")
+        ));
+        assert!(error.source().is_some());
+
+        let error = process_project_folder(&missing_path, "missing").unwrap_err();
+        assert!(matches!(
+            &error,
+            ProjectProcessorError::WalkDirError { path, .. } if *path == missing_path
+        ));
+        assert!(error.to_string().contains("missing"));
+    }
 }
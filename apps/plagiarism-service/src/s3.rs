@@ -1,6 +1,7 @@
-use dotenvy::dotenv;
+use crate::storage::StoreError;
 use s3::bucket::Bucket;
 use s3::creds::Credentials;
+use s3::error::S3Error;
 use s3::region::Region;
 use std::env;
 
@@ -14,8 +15,6 @@ pub struct S3Config {
 
 impl S3Config {
     pub fn from_env() -> Result<Self, String> {
-        dotenv().ok();
-
         let endpoint =
             env::var("MINIO_ENDPOINT").map_err(|e| format!("MINIO_ENDPOINT not set: {e}"))?;
         let bucket_name =
@@ -49,43 +48,63 @@ impl S3Config {
     }
 }
 
-pub async fn get_file_from_s3(key: &str) -> Result<Vec<u8>, String> {
-    let config = S3Config::from_env()?;
-
+/// Builds the client of the configured bucket once, and checks that its objects can be
+/// listed so that a wrong endpoint, credentials or bucket name is reported at startup
+/// rather than on the first check.
+pub async fn connect_bucket(config: &S3Config) -> Result<Box<Bucket>, String> {
     let custom_region = Region::Custom {
         region: config.region.to_string(),
-        endpoint: config.endpoint,
+        endpoint: config.endpoint.clone(),
     };
 
-    let bucket = Bucket::new(&config.bucket_name, custom_region, config.credentials)
-        .map_err(|e| format!("Bucket initialization failed: {e}"))?;
+    let bucket = Bucket::new(
+        &config.bucket_name,
+        custom_region,
+        config.credentials.clone(),
+    )
+    .map_err(|e| format!("Bucket initialization failed: {e}"))?
+    .with_path_style();
 
-    let bucket = bucket.with_path_style();
+    bucket
+        .list_page(String::new(), None, None, None, Some(1))
+        .await
+        .map_err(|e| {
+            format!(
+                "Cannot access bucket '{}' at {}, check S3_BUCKET_NAME, MINIO_ENDPOINT and the credentials: {}",
+                config.bucket_name, config.endpoint, e
+            )
+        })?;
+
+    Ok(bucket)
+}
 
+/// Whether a request that failed with `error` may succeed if sent again.
+fn is_transient(error: &S3Error) -> bool {
+    match error {
+        S3Error::HttpFailWithBody(status, _) => matches!(status, 408 | 429 | 500..),
+        S3Error::HttpFail | S3Error::Io(_) | S3Error::Hyper(_) => true,
+        _ => false,
+    }
+}
+
+pub async fn get_file_from_s3(bucket: &Bucket, key: &str) -> Result<Vec<u8>, StoreError> {
     let response = bucket.get_object(key).await.map_err(|e| {
-        format!(
+        let message = format!(
             "Failed to get object '{}' from bucket '{}': {}",
-            key, config.bucket_name, e
-        )
+            key, bucket.name, e
+        );
+        if is_transient(&e) {
+            StoreError::Transient(message)
+        } else {
+            StoreError::Permanent(message)
+        }
     })?;
 
     Ok(response.bytes().to_vec())
 }
 
-pub async fn list_files_in_directory(prefix: &str) -> Result<Vec<String>, String> {
-    let config = S3Config::from_env()?;
-
-    let custom_region = Region::Custom {
-        region: config.region.to_string(),
-        endpoint: config.endpoint,
-    };
-
-    let bucket = Bucket::new(&config.bucket_name, custom_region, config.credentials)
-        .map_err(|e| format!("Bucket initialization failed: {e}"))?;
-
-    let bucket = bucket.with_path_style();
-
-    println!("Listing files in directory: {bucket:?}");
+pub async fn list_files_in_directory(bucket: &Bucket, prefix: &str) -> Result<Vec<String>, String> {
+    println!("Listing files in directory: {prefix}");
 
     let results = bucket
         .list(prefix.to_string(), None)
@@ -100,3 +119,38 @@ pub async fn list_files_in_directory(prefix: &str) -> Result<Vec<String>, String
 
     Ok(file_keys)
 }
+
+pub async fn put_file_to_s3(
+    bucket: &Bucket,
+    key: &str,
+    content: &[u8],
+    content_type: &str,
+) -> Result<(), String> {
+    bucket
+        .put_object_with_content_type(key, content, content_type)
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to put object '{}' into bucket '{}': {}",
+                key, bucket.name, e
+            )
+        })?;
+
+    Ok(())
+}
+
+/// Size in bytes of the object stored under `key`, or `None` when there is no such object.
+pub async fn head_file_in_s3(bucket: &Bucket, key: &str) -> Result<Option<u64>, String> {
+    match bucket.head_object(key).await {
+        Ok((_, 404)) | Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+        Ok((head, _)) => Ok(Some(
+            head.content_length
+                .and_then(|length| u64::try_from(length).ok())
+                .unwrap_or(0),
+        )),
+        Err(e) => Err(format!(
+            "Failed to head object '{}' in bucket '{}': {}",
+            key, bucket.name, e
+        )),
+    }
+}
@@ -0,0 +1,306 @@
+use crate::s3::{self, S3Config};
+use ::s3::bucket::Bucket;
+use actix_web::web;
+use async_trait::async_trait;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Directory used by the local backend when `LOCAL_STORAGE_PATH` is not set.
+const DEFAULT_LOCAL_STORAGE_PATH: &str = "./storage";
+
+/// Metadata of a stored object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    pub size_bytes: u64,
+}
+
+/// Why an object could not be downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// The download may succeed if retried, such as after a timeout or a 5xx response.
+    Transient(String),
+    /// Retrying cannot help, such as when the object does not exist.
+    Permanent(String),
+}
+
+impl StoreError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, StoreError::Transient(_))
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Transient(message) | StoreError::Permanent(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+/// Object storage holding the zipped project submissions.
+///
+/// Keys are `/`-separated paths such as `project-1/promo-2/step-3/42-1747262120000.zip`.
+#[async_trait]
+pub trait SubmissionStore: Send + Sync {
+    /// Lists every object key starting with `prefix`.
+    async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, String>;
+
+    /// Downloads the full content of the object stored under `key`.
+    async fn get_object(&self, key: &str) -> Result<Vec<u8>, StoreError>;
+
+    /// Stores `content` under `key`, replacing any existing object.
+    async fn put_object(&self, key: &str, content: Vec<u8>) -> Result<(), String>;
+
+    /// Metadata of the object stored under `key`, or `None` when there is no such object.
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMetadata>, String>;
+}
+
+/// Builds the store selected by `STORAGE_BACKEND`: `s3` (the default), connected to the
+/// configured bucket, or `local`, which keeps objects below `LOCAL_STORAGE_PATH`.
+pub async fn store_from_env() -> Result<Arc<dyn SubmissionStore>, String> {
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("s3") | Err(_) => Ok(Arc::new(S3SubmissionStore::connect().await?)),
+        Ok("local") => {
+            let root = env::var("LOCAL_STORAGE_PATH")
+                .unwrap_or_else(|_| DEFAULT_LOCAL_STORAGE_PATH.to_string());
+            println!("Storing submissions below {root}");
+            Ok(Arc::new(LocalSubmissionStore::new(root)))
+        }
+        Ok(other) => Err(format!(
+            "Unknown STORAGE_BACKEND '{other}', expected 's3' or 'local'"
+        )),
+    }
+}
+
+/// Submission store backed by the MinIO/S3 bucket configured through the environment.
+pub struct S3SubmissionStore {
+    bucket: Box<Bucket>,
+}
+
+impl S3SubmissionStore {
+    /// Connects to the bucket configured through the environment, failing if it cannot
+    /// be accessed.
+    pub async fn connect() -> Result<Self, String> {
+        let config = S3Config::from_env()?;
+        let bucket = s3::connect_bucket(&config).await?;
+        println!(
+            "Connected to bucket '{}' at {}",
+            config.bucket_name, config.endpoint
+        );
+        Ok(Self { bucket })
+    }
+}
+
+#[async_trait]
+impl SubmissionStore for S3SubmissionStore {
+    async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, String> {
+        s3::list_files_in_directory(&self.bucket, prefix).await
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        s3::get_file_from_s3(&self.bucket, key).await
+    }
+
+    async fn put_object(&self, key: &str, content: Vec<u8>) -> Result<(), String> {
+        s3::put_file_to_s3(&self.bucket, key, &content, "application/zip").await
+    }
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMetadata>, String> {
+        Ok(s3::head_file_in_s3(&self.bucket, key)
+            .await?
+            .map(|size_bytes| ObjectMetadata { size_bytes }))
+    }
+}
+
+/// Submission store keeping each object in a file below a local directory, at the path
+/// given by its key. Meant for development and tests, where no object store is running.
+pub struct LocalSubmissionStore {
+    root: PathBuf,
+}
+
+impl LocalSubmissionStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// File holding the object stored under `key`, refusing keys that would point
+    /// outside of the root directory.
+    fn path_of(&self, key: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(key);
+        let is_contained = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if key.is_empty() || !is_contained {
+            return Err(format!("Invalid object key '{key}'"));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+/// Runs blocking file system work on the thread pool reserved for it, so that it does
+/// not stall the async workers.
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, String> {
+    web::block(work)
+        .await
+        .map_err(|e| format!("Storage operation was interrupted: {e}"))
+}
+
+/// Keys of the files below `root` starting with `prefix`, sorted.
+fn list_local_keys(root: &Path, prefix: &str) -> Result<Vec<String>, String> {
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    let mut keys = Vec::new();
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry.map_err(|e| format!("Failed to list '{prefix}': {e}"))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let key = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if key.starts_with(prefix) {
+            keys.push(key);
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+#[async_trait]
+impl SubmissionStore for LocalSubmissionStore {
+    async fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+        run_blocking(move || list_local_keys(&root, &prefix)).await?
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        let path = self.path_of(key).map_err(StoreError::Permanent)?;
+        run_blocking(move || fs::read(path))
+            .await
+            .map_err(StoreError::Transient)?
+            .map_err(|e| {
+                let message = format!("Failed to read object '{key}': {e}");
+                match e.kind() {
+                    io::ErrorKind::Interrupted
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock => StoreError::Transient(message),
+                    _ => StoreError::Permanent(message),
+                }
+            })
+    }
+
+    async fn put_object(&self, key: &str, content: Vec<u8>) -> Result<(), String> {
+        let path = self.path_of(key)?;
+        let key = key.to_string();
+        run_blocking(move || {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create directory for object '{key}': {e}"))?;
+            }
+            fs::write(&path, content).map_err(|e| format!("Failed to write object '{key}': {e}"))
+        })
+        .await?
+    }
+
+    async fn head_object(&self, key: &str) -> Result<Option<ObjectMetadata>, String> {
+        let path = self.path_of(key)?;
+        match run_blocking(move || fs::metadata(path)).await? {
+            Ok(metadata) if metadata.is_file() => Ok(Some(ObjectMetadata {
+                size_bytes: metadata.len(),
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read metadata of object '{key}': {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_local_store_round_trips_objects() {
+        let root = tempfile::tempdir().unwrap();
+        let store = LocalSubmissionStore::new(root.path());
+
+        assert!(store.list_prefix("project-1/").await.unwrap().is_empty());
+        assert_eq!(store.head_object("project-1/a.zip").await.unwrap(), None);
+
+        for key in [
+            "project-1/promo-2/step-3/10-1.zip",
+            "project-1/promo-2/step-3/11-1.zip",
+            "project-1/promo-3/step-3/12-1.zip",
+        ] {
+            store
+                .put_object(key, key.as_bytes().to_vec())
+                .await
+                .unwrap();
+        }
+        store
+            .put_object("project-1/promo-2/step-3/11-1.zip", b"replaced".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.list_prefix("project-1/promo-2/").await.unwrap(),
+            vec![
+                "project-1/promo-2/step-3/10-1.zip",
+                "project-1/promo-2/step-3/11-1.zip",
+            ]
+        );
+        assert_eq!(store.list_prefix("project-1/").await.unwrap().len(), 3);
+        assert_eq!(
+            store
+                .get_object("project-1/promo-2/step-3/11-1.zip")
+                .await
+                .unwrap(),
+            b"replaced"
+        );
+        assert_eq!(
+            store
+                .head_object("project-1/promo-2/step-3/11-1.zip")
+                .await
+                .unwrap(),
+            Some(ObjectMetadata { size_bytes: 8 })
+        );
+        assert_eq!(store.head_object("project-1/promo-2").await.unwrap(), None);
+        assert!(matches!(
+            store.get_object("project-1/missing.zip").await,
+            Err(StoreError::Permanent(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_local_store_rejects_keys_outside_its_root() {
+        let root = tempfile::tempdir().unwrap();
+        let store = LocalSubmissionStore::new(root.path().join("store"));
+
+        for key in [
+            "",
+            "../escape.zip",
+            "project-1/../../escape.zip",
+            "/etc/passwd",
+        ] {
+            assert!(store.put_object(key, Vec::new()).await.is_err(), "{key}");
+            assert!(store.get_object(key).await.is_err(), "{key}");
+            assert!(store.head_object(key).await.is_err(), "{key}");
+        }
+        assert!(!root.path().join("escape.zip").exists());
+    }
+}
@@ -0,0 +1,792 @@
+use crate::algorithm::tokenize_with_spans;
+use crate::project_processor::SourceLanguage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Identifier,
+    Literal,
+    Operator,
+    Punctuation,
+    /// A word of prose, produced for text and unsupported languages.
+    Word,
+}
+
+/// A lexical token along with the byte range it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: (usize, usize),
+}
+
+/// How tokens are turned into the symbols that get fingerprinted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenNormalization {
+    /// Tokens are fingerprinted as written.
+    Raw,
+    /// Identifiers and literals are replaced by placeholders, so consistently renamed
+    /// variables and changed constants still produce the same fingerprints.
+    Renaming,
+}
+
+impl Token {
+    /// The symbol this token contributes to fingerprints under `normalization`.
+    pub fn normalized_text(&self, normalization: TokenNormalization) -> &str {
+        match (normalization, self.kind) {
+            (TokenNormalization::Renaming, TokenKind::Identifier) => "$ID",
+            (TokenNormalization::Renaming, TokenKind::Literal) => {
+                if self
+                    .text
+                    .starts_with(|c: char| c.is_ascii_digit() || c == '.')
+                {
+                    "$NUM"
+                } else {
+                    "$STR"
+                }
+            }
+            _ => &self.text,
+        }
+    }
+}
+
+/// Splits source code into tokens, dropping whitespace and comments.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, source: &str) -> Vec<Token>;
+}
+
+/// Returns the tokenizer for files written in `language`.
+pub fn tokenizer_for(language: &SourceLanguage) -> &'static dyn Tokenizer {
+    match language {
+        SourceLanguage::C => &CTokenizer,
+        SourceLanguage::Python => &PythonTokenizer,
+        SourceLanguage::Rust => &RustTokenizer,
+        SourceLanguage::Text | SourceLanguage::Unknown => &TextTokenizer,
+    }
+}
+
+/// Fallback for prose and unsupported languages: lowercased alphanumeric words.
+pub struct TextTokenizer;
+
+impl Tokenizer for TextTokenizer {
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        tokenize_with_spans(source)
+            .into_iter()
+            .map(|(text, span)| Token {
+                kind: TokenKind::Word,
+                text,
+                span,
+            })
+            .collect()
+    }
+}
+
+/// Characters that only group or separate code; every other symbol is an operator.
+const PUNCTUATION: &str = "()[]{},;";
+
+const C_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Bool",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+];
+
+/// Longest operators first so that the scanner always takes the longest match.
+const C_OPERATORS: &[&str] = &[
+    "<<=", ">>=", "...", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "&=", "|=", "^=", "##",
+];
+
+const C_STRING_PREFIXES: &[&str] = &["L", "u", "U", "u8"];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+const PYTHON_OPERATORS: &[&str] = &[
+    "**=", "//=", ">>=", "<<=", "...", "->", ":=", "**", "//", "<<", ">>", "<=", ">=", "==", "!=",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "@=",
+];
+
+const PYTHON_STRING_PREFIXES: &[&str] = &[
+    "r", "u", "b", "f", "br", "rb", "fr", "rf", "R", "U", "B", "F", "Br", "bR", "BR", "rB", "Rb",
+    "RB", "Fr", "fR", "FR", "rF", "Rf", "RF",
+];
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+const RUST_OPERATORS: &[&str] = &[
+    "<<=", ">>=", "...", "..=", "..", "::", "->", "=>", "<<", ">>", "<=", ">=", "==", "!=", "&&",
+    "||", "+=", "-=", "*=", "/=", "%=", "^=", "&=", "|=",
+];
+
+/// Cursor over a source text shared by the language lexers.
+struct Scanner<'a> {
+    source: &'a str,
+    pos: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.rest().starts_with(prefix)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        let matched = self.starts_with(prefix);
+        if matched {
+            self.pos += prefix.len();
+        }
+        matched
+    }
+
+    fn eat_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
+        }
+    }
+
+    fn push(&mut self, kind: TokenKind, start: usize) {
+        self.tokens.push(Token {
+            kind,
+            text: self.source[start..self.pos].to_string(),
+            span: (start, self.pos),
+        });
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        self.eat_while(char::is_whitespace);
+        self.pos > start
+    }
+
+    /// Skips a comment running to the end of the line if one starts here.
+    fn skip_line_comment(&mut self, marker: &str) -> bool {
+        if !self.eat(marker) {
+            return false;
+        }
+        self.eat_while(|c| c != '\n');
+        true
+    }
+
+    /// Skips a `/* ... */` comment if one starts here. Unterminated comments run to the
+    /// end of the source.
+    fn skip_block_comment(&mut self, nested: bool) -> bool {
+        if !self.eat("/*") {
+            return false;
+        }
+        let mut depth = 1;
+        while depth > 0 && self.peek().is_some() {
+            if self.eat("*/") {
+                depth -= 1;
+            } else if nested && self.eat("/*") {
+                depth += 1;
+            } else {
+                self.bump();
+            }
+        }
+        true
+    }
+
+    /// Consumes the body of a quoted literal whose opening `quote` was already consumed,
+    /// up to and including the closing quote. Backslashes escape the next character.
+    /// Unless `multiline`, an unescaped line break ends the literal early so a stray
+    /// quote does not swallow the rest of the file.
+    fn eat_quoted_body(&mut self, quote: &str, multiline: bool) {
+        while let Some(c) = self.peek() {
+            if self.eat(quote) {
+                return;
+            }
+            if c == '\n' && !multiline {
+                return;
+            }
+            self.bump();
+            if c == '\\' {
+                self.bump();
+            }
+        }
+    }
+
+    /// Consumes a numeric literal: digits, radix prefixes, suffixes, a fractional part
+    /// and a signed exponent.
+    fn eat_number(&mut self) {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(c) if c.is_alphanumeric() || c == '_' => {
+                    self.bump();
+                    let is_hex = self.source[start..].starts_with("0x")
+                        || self.source[start..].starts_with("0X");
+                    let is_exponent = if is_hex {
+                        matches!(c, 'p' | 'P')
+                    } else {
+                        matches!(c, 'e' | 'E')
+                    };
+                    if is_exponent
+                        && matches!(self.peek(), Some('+' | '-'))
+                        && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit())
+                    {
+                        self.bump();
+                    }
+                }
+                Some('.') if self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn eat_identifier(&mut self) -> &'a str {
+        let start = self.pos;
+        self.eat_while(is_identifier_continue);
+        &self.source[start..self.pos]
+    }
+
+    /// Consumes the longest of `operators` starting here, or else a single symbol.
+    /// Returns `false` at the end of the source.
+    fn push_symbol(&mut self, operators: &[&str]) -> bool {
+        let start = self.pos;
+        if let Some(operator) = operators.iter().find(|op| self.starts_with(op)) {
+            self.pos += operator.len();
+            self.push(TokenKind::Operator, start);
+            return true;
+        }
+        let Some(c) = self.bump() else {
+            return false;
+        };
+        let kind = if PUNCTUATION.contains(c) {
+            TokenKind::Punctuation
+        } else {
+            TokenKind::Operator
+        };
+        self.push(kind, start);
+        true
+    }
+
+    fn finish(self) -> Vec<Token> {
+        self.tokens
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn starts_number(scanner: &Scanner, leading_dot: bool) -> bool {
+    match scanner.peek() {
+        Some(c) if c.is_ascii_digit() => true,
+        Some('.') => leading_dot && scanner.peek_nth(1).is_some_and(|c| c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+fn keyword_or_identifier(keywords: &[&str], word: &str) -> TokenKind {
+    if keywords.contains(&word) {
+        TokenKind::Keyword
+    } else {
+        TokenKind::Identifier
+    }
+}
+
+/// Lexer for C sources and headers. Preprocessor directives are tokenized like code.
+pub struct CTokenizer;
+
+impl Tokenizer for CTokenizer {
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(source);
+        loop {
+            if scanner.skip_whitespace()
+                || scanner.skip_line_comment("//")
+                || scanner.skip_block_comment(false)
+            {
+                continue;
+            }
+            let start = scanner.pos;
+            match scanner.peek() {
+                None => break,
+                Some(quote @ ('"' | '\'')) => {
+                    scanner.bump();
+                    scanner.eat_quoted_body(quote.encode_utf8(&mut [0; 4]), false);
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some(c) if is_identifier_start(c) => {
+                    let word = scanner.eat_identifier();
+                    if C_STRING_PREFIXES.contains(&word)
+                        && let Some(quote @ ('"' | '\'')) = scanner.peek()
+                    {
+                        scanner.bump();
+                        scanner.eat_quoted_body(quote.encode_utf8(&mut [0; 4]), false);
+                        scanner.push(TokenKind::Literal, start);
+                    } else {
+                        scanner.push(keyword_or_identifier(C_KEYWORDS, word), start);
+                    }
+                }
+                Some(_) if starts_number(&scanner, true) => {
+                    scanner.eat_number();
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some(_) => {
+                    scanner.push_symbol(C_OPERATORS);
+                }
+            }
+        }
+        scanner.finish()
+    }
+}
+
+/// Lexer for Python sources. Docstrings are kept as string literals.
+pub struct PythonTokenizer;
+
+impl PythonTokenizer {
+    /// Consumes a string literal whose prefix, if any, was already consumed.
+    fn eat_string(scanner: &mut Scanner, quote: char) {
+        let triple = if quote == '"' { "\"\"\"" } else { "'''" };
+        if scanner.eat(triple) {
+            scanner.eat_quoted_body(triple, true);
+        } else {
+            scanner.bump();
+            scanner.eat_quoted_body(quote.encode_utf8(&mut [0; 4]), false);
+        }
+    }
+}
+
+impl Tokenizer for PythonTokenizer {
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(source);
+        loop {
+            if scanner.skip_whitespace() || scanner.skip_line_comment("#") {
+                continue;
+            }
+            let start = scanner.pos;
+            match scanner.peek() {
+                None => break,
+                Some(quote @ ('"' | '\'')) => {
+                    Self::eat_string(&mut scanner, quote);
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some(c) if is_identifier_start(c) => {
+                    let word = scanner.eat_identifier();
+                    if PYTHON_STRING_PREFIXES.contains(&word)
+                        && let Some(quote @ ('"' | '\'')) = scanner.peek()
+                    {
+                        Self::eat_string(&mut scanner, quote);
+                        scanner.push(TokenKind::Literal, start);
+                    } else {
+                        scanner.push(keyword_or_identifier(PYTHON_KEYWORDS, word), start);
+                    }
+                }
+                Some(_) if starts_number(&scanner, true) => {
+                    scanner.eat_number();
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some(_) => {
+                    scanner.push_symbol(PYTHON_OPERATORS);
+                }
+            }
+        }
+        scanner.finish()
+    }
+}
+
+/// Lexer for Rust sources. Lifetimes and labels are reported as identifiers.
+pub struct RustTokenizer;
+
+impl RustTokenizer {
+    /// Consumes a raw string body (`#`s then the opening quote onwards) if one starts
+    /// here.
+    fn eat_raw_string(scanner: &mut Scanner) -> bool {
+        let hashes = scanner.rest().chars().take_while(|&c| c == '#').count();
+        if scanner.peek_nth(hashes) != Some('"') {
+            return false;
+        }
+        scanner.pos += hashes + 1;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        while scanner.peek().is_some() && !scanner.eat(&terminator) {
+            scanner.bump();
+        }
+        true
+    }
+
+    /// Consumes a character literal or a lifetime, starting at the `'`.
+    fn eat_quote(scanner: &mut Scanner) -> TokenKind {
+        scanner.bump();
+        let is_char_literal = scanner.peek() == Some('\\') || scanner.peek_nth(1) == Some('\'');
+        if is_char_literal {
+            scanner.eat_quoted_body("'", false);
+            TokenKind::Literal
+        } else {
+            scanner.eat_identifier();
+            TokenKind::Identifier
+        }
+    }
+}
+
+impl Tokenizer for RustTokenizer {
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(source);
+        loop {
+            if scanner.skip_whitespace()
+                || scanner.skip_line_comment("//")
+                || scanner.skip_block_comment(true)
+            {
+                continue;
+            }
+            let start = scanner.pos;
+            match scanner.peek() {
+                None => break,
+                Some('"') => {
+                    scanner.bump();
+                    scanner.eat_quoted_body("\"", true);
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some('\'') => {
+                    let kind = Self::eat_quote(&mut scanner);
+                    scanner.push(kind, start);
+                }
+                Some(c) if is_identifier_start(c) => {
+                    let word = scanner.eat_identifier();
+                    let kind = match (word, scanner.peek()) {
+                        ("b" | "c", Some('"')) => {
+                            scanner.bump();
+                            scanner.eat_quoted_body("\"", true);
+                            TokenKind::Literal
+                        }
+                        ("b", Some('\'')) => Self::eat_quote(&mut scanner),
+                        ("r" | "br" | "cr", Some('"' | '#'))
+                            if Self::eat_raw_string(&mut scanner) =>
+                        {
+                            TokenKind::Literal
+                        }
+                        ("r", Some('#'))
+                            if scanner.peek_nth(1).is_some_and(is_identifier_start) =>
+                        {
+                            scanner.bump();
+                            scanner.eat_identifier();
+                            TokenKind::Identifier
+                        }
+                        _ => keyword_or_identifier(RUST_KEYWORDS, word),
+                    };
+                    scanner.push(kind, start);
+                }
+                Some(_) if starts_number(&scanner, false) => {
+                    scanner.eat_number();
+                    scanner.push(TokenKind::Literal, start);
+                }
+                Some(_) => {
+                    scanner.push_symbol(RUST_OPERATORS);
+                }
+            }
+        }
+        scanner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(tokenizer: &dyn Tokenizer, source: &str) -> Vec<(TokenKind, String)> {
+        tokenizer
+            .tokenize(source)
+            .into_iter()
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    fn texts(tokenizer: &dyn Tokenizer, source: &str) -> Vec<String> {
+        tokenizer
+            .tokenize(source)
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_c_tokenizer_emits_typed_tokens() {
+        use TokenKind::*;
+
+        let tokens = lex(&CTokenizer, "int x = a+b; // sum\n");
+        assert_eq!(
+            tokens,
+            vec![
+                (Keyword, "int".to_string()),
+                (Identifier, "x".to_string()),
+                (Operator, "=".to_string()),
+                (Identifier, "a".to_string()),
+                (Operator, "+".to_string()),
+                (Identifier, "b".to_string()),
+                (Punctuation, ";".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_c_tokenizer_keeps_operators_apart_from_whitespace() {
+        assert_ne!(texts(&CTokenizer, "a+b"), texts(&CTokenizer, "a b"));
+        assert_eq!(texts(&CTokenizer, "a+b"), texts(&CTokenizer, "a + b"));
+        assert_eq!(
+            texts(&CTokenizer, "x<<=2;p->next++"),
+            ["x", "<<=", "2", ";", "p", "->", "next", "++"]
+        );
+    }
+
+    #[test]
+    fn test_c_tokenizer_drops_comments_and_reads_literals() {
+        let source =
+            "/* header\n comment */ printf(\"a // b \\\" c\", 'x', L\"wide\", 0x1Fu, 1.5e-3f);";
+        assert_eq!(
+            texts(&CTokenizer, source),
+            [
+                "printf",
+                "(",
+                "\"a // b \\\" c\"",
+                ",",
+                "'x'",
+                ",",
+                "L\"wide\"",
+                ",",
+                "0x1Fu",
+                ",",
+                "1.5e-3f",
+                ")",
+                ";"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_c_tokenizer_stops_unterminated_string_at_line_end() {
+        let tokens = texts(&CTokenizer, "char c = 'a;\nint y;");
+        assert_eq!(tokens, ["char", "c", "=", "'a;", "int", "y", ";"]);
+    }
+
+    #[test]
+    fn test_python_tokenizer_handles_strings_and_comments() {
+        use TokenKind::*;
+
+        let source = "def f(x):  # doc\n    \"\"\"Doc # not a comment\n    \"\"\"\n    return rb'\\x00' ** 2\n";
+        assert_eq!(
+            lex(&PythonTokenizer, source),
+            vec![
+                (Keyword, "def".to_string()),
+                (Identifier, "f".to_string()),
+                (Punctuation, "(".to_string()),
+                (Identifier, "x".to_string()),
+                (Punctuation, ")".to_string()),
+                (Operator, ":".to_string()),
+                (Literal, "\"\"\"Doc # not a comment\n    \"\"\"".to_string()),
+                (Keyword, "return".to_string()),
+                (Literal, "rb'\\x00'".to_string()),
+                (Operator, "**".to_string()),
+                (Literal, "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_python_tokenizer_reads_numbers_and_walrus() {
+        assert_eq!(
+            texts(&PythonTokenizer, "if (n := .5e+2 // 1_000):"),
+            ["if", "(", "n", ":=", ".5e+2", "//", "1_000", ")", ":"]
+        );
+    }
+
+    #[test]
+    fn test_rust_tokenizer_handles_lifetimes_chars_and_raw_strings() {
+        use TokenKind::*;
+
+        let source = "fn f<'a>(s: &'a str) -> char { /* outer /* inner */ */ let _ = r#\"a \"quoted\" b\"#; '\\n' }";
+        assert_eq!(
+            lex(&RustTokenizer, source),
+            vec![
+                (Keyword, "fn".to_string()),
+                (Identifier, "f".to_string()),
+                (Operator, "<".to_string()),
+                (Identifier, "'a".to_string()),
+                (Operator, ">".to_string()),
+                (Punctuation, "(".to_string()),
+                (Identifier, "s".to_string()),
+                (Operator, ":".to_string()),
+                (Operator, "&".to_string()),
+                (Identifier, "'a".to_string()),
+                (Identifier, "str".to_string()),
+                (Punctuation, ")".to_string()),
+                (Operator, "->".to_string()),
+                (Identifier, "char".to_string()),
+                (Punctuation, "{".to_string()),
+                (Keyword, "let".to_string()),
+                (Identifier, "_".to_string()),
+                (Operator, "=".to_string()),
+                (Literal, "r#\"a \"quoted\" b\"#".to_string()),
+                (Punctuation, ";".to_string()),
+                (Literal, "'\\n'".to_string()),
+                (Punctuation, "}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rust_tokenizer_reads_ranges_and_byte_literals() {
+        assert_eq!(
+            texts(
+                &RustTokenizer,
+                "for i in 0..=9 { v.0 += b'x' as u8 + 1.5; }"
+            ),
+            [
+                "for", "i", "in", "0", "..=", "9", "{", "v", ".", "0", "+=", "b'x'", "as", "u8",
+                "+", "1.5", ";", "}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_spans_point_into_source() {
+        let source = "é = 'ü' # ☃\nx";
+        for token in PythonTokenizer.tokenize(source) {
+            assert_eq!(&source[token.span.0..token.span.1], token.text);
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_for_selects_by_language() {
+        let source = "x = 1 # note";
+        assert_eq!(
+            texts(tokenizer_for(&SourceLanguage::Python), source),
+            ["x", "=", "1"]
+        );
+        assert_eq!(
+            texts(tokenizer_for(&SourceLanguage::C), source),
+            ["x", "=", "1", "#", "note"]
+        );
+        assert_eq!(
+            texts(tokenizer_for(&SourceLanguage::Text), "Hello, World"),
+            ["hello", "world"]
+        );
+    }
+
+    #[test]
+    fn test_renaming_normalization_hides_identifiers_and_literals() {
+        let original = "int total = count * 2; printf(\"%d\\n\", total);";
+        let renamed = "int sum = n * 10; printf(\"result: %d\\n\", sum);";
+        let normalize = |source: &str, normalization| -> Vec<String> {
+            CTokenizer
+                .tokenize(source)
+                .iter()
+                .map(|token| token.normalized_text(normalization).to_string())
+                .collect()
+        };
+
+        assert_ne!(
+            normalize(original, TokenNormalization::Raw),
+            normalize(renamed, TokenNormalization::Raw)
+        );
+        assert_eq!(
+            normalize(original, TokenNormalization::Renaming),
+            normalize(renamed, TokenNormalization::Renaming)
+        );
+        assert_eq!(
+            normalize("x = 1.5 + 'a'", TokenNormalization::Renaming),
+            ["$ID", "=", "$NUM", "+", "$STR"]
+        );
+    }
+
+    #[test]
+    fn test_renaming_normalization_keeps_keywords_operators_and_words() {
+        let normalize = |tokenizer: &dyn Tokenizer, source: &str| -> Vec<String> {
+            tokenizer
+                .tokenize(source)
+                .iter()
+                .map(|token| {
+                    token
+                        .normalized_text(TokenNormalization::Renaming)
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            normalize(&PythonTokenizer, "return a + b"),
+            ["return", "$ID", "+", "$ID"]
+        );
+        assert_eq!(normalize(&TextTokenizer, "Hello world"), ["hello", "world"]);
+    }
+}
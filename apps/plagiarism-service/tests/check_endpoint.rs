@@ -0,0 +1,209 @@
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::{App, test, web};
+use apistos::app::OpenApiWrapper;
+use apistos::spec::Spec;
+use plagiarism_service::api::{AppState, routes};
+use plagiarism_service::extraction::ExtractionLimits;
+use plagiarism_service::jobs::JobManager;
+use plagiarism_service::storage::{LocalSubmissionStore, SubmissionStore};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use zip::write::SimpleFileOptions;
+
+const SOLUTION: &str = "def add(a, b):\n    return a + b\n\nprint(add(1, 2))\n";
+const OTHER: &str = "for i in range(10):\n    if i % 2 == 0:\n        print(i)\n";
+
+fn zip_submission(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in files {
+        writer
+            .start_file(*name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Stores a zipped submission at `key` below `root`, as the local store lays objects out.
+fn store_submission(root: &Path, key: &str, files: &[(&str, &str)]) {
+    let path = root.join(key);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, zip_submission(files)).unwrap();
+}
+
+async fn service(
+    root: &Path,
+    extract_dir: &Path,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let store: Arc<dyn SubmissionStore> = Arc::new(LocalSubmissionStore::new(root));
+    let jobs = Arc::new(
+        JobManager::new(
+            1,
+            2,
+            4,
+            ExtractionLimits::default(),
+            Arc::clone(&store),
+            extract_dir,
+        )
+        .unwrap(),
+    );
+    test::init_service(
+        App::new()
+            .document(Spec::default())
+            .app_data(web::Data::new(AppState { jobs, store }))
+            .service(routes())
+            .build("/docs"),
+    )
+    .await
+}
+
+/// Queues a check of `body` and polls its status until the job is over.
+async fn run_check(
+    app: &impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error>,
+    body: Value,
+) -> Value {
+    let request = test::TestRequest::post()
+        .uri("/plagiarism/checks")
+        .set_json(body)
+        .to_request();
+    let response = test::call_service(app, request).await;
+    assert_eq!(response.status(), 202);
+    let accepted: Value = test::read_body_json(response).await;
+    let job_id = accepted["jobId"].as_str().unwrap().to_string();
+
+    for _ in 0..500 {
+        let request = test::TestRequest::get()
+            .uri(&format!("/plagiarism/checks/{job_id}"))
+            .to_request();
+        let status: Value = test::call_and_read_body_json(app, request).await;
+        if status["status"] == "done" || status["status"] == "failed" {
+            return status;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Job {job_id} did not finish");
+}
+
+#[actix_web::test]
+async fn test_check_endpoint_reports_copies_from_local_store() {
+    let root = tempfile::tempdir().unwrap();
+    let extract_dir = tempfile::tempdir().unwrap();
+    for (submission, content) in [("10-1", SOLUTION), ("11-1", SOLUTION), ("12-1", OTHER)] {
+        store_submission(
+            root.path(),
+            &format!("project-1/promo-2/step-3/{submission}.zip"),
+            &[("main.py", content)],
+        );
+    }
+    store_submission(
+        root.path(),
+        "project-1/promo-9/step-3/13-1.zip",
+        &[("main.py", SOLUTION)],
+    );
+    let app = service(root.path(), extract_dir.path()).await;
+
+    let status = run_check(
+        &app,
+        json!({"projectId": "1", "promotionId": "2", "step": "3"}),
+    )
+    .await;
+    assert_eq!(status["status"], "done");
+    let report = &status["result"];
+    let folders: Vec<&str> = report["folderResults"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|folder| folder["folderName"].as_str().unwrap())
+        .collect();
+    assert_eq!(folders, vec!["10-1", "11-1", "12-1"]);
+    assert!(
+        report["folderResults"][0]["plagiarismPercentage"]
+            .as_f64()
+            .unwrap()
+            > 90.0
+    );
+    assert_eq!(report["folderResults"][2]["plagiarismPercentage"], 0.0);
+    assert_eq!(report["clusters"][0]["members"], json!(["10-1", "11-1"]));
+
+    let job_id = status["jobId"].as_str().unwrap();
+    let request = test::TestRequest::get()
+        .uri(&format!("/plagiarism/checks/{job_id}/report/10-1/11-1"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 200);
+    let html = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    assert!(html.contains("main.py"));
+}
+
+#[actix_web::test]
+async fn test_check_endpoint_excludes_template_registered_through_the_api() {
+    let root = tempfile::tempdir().unwrap();
+    let extract_dir = tempfile::tempdir().unwrap();
+    for submission in ["10-1", "11-1"] {
+        store_submission(
+            root.path(),
+            &format!("project-1/promo-2/step-3/{submission}.zip"),
+            &[("main.py", SOLUTION)],
+        );
+    }
+    let app = service(root.path(), extract_dir.path()).await;
+
+    let request = test::TestRequest::put()
+        .uri("/plagiarism/templates/1/2/3")
+        .set_payload(zip_submission(&[("main.py", SOLUTION)]))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+    assert!(
+        root.path()
+            .join("project-1/promo-2/step-3/base-code.zip")
+            .is_file()
+    );
+
+    let status = run_check(
+        &app,
+        json!({"projectId": "1", "promotionId": "2", "step": "3"}),
+    )
+    .await;
+    assert_eq!(status["status"], "done");
+    let report = &status["result"];
+    assert_eq!(report["templateExcluded"], true);
+    assert_eq!(report["folderResults"].as_array().unwrap().len(), 2);
+    assert_eq!(report["folderResults"][0]["plagiarismPercentage"], 0.0);
+}
+
+#[actix_web::test]
+async fn test_check_endpoint_rejects_invalid_requests() {
+    let root = tempfile::tempdir().unwrap();
+    let extract_dir = tempfile::tempdir().unwrap();
+    let app = service(root.path(), extract_dir.path()).await;
+
+    for body in [
+        json!({"projectId": "", "promotionId": "2", "step": "3"}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "config": {"mossK": 0}}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": ["2"]}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": [""]}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": ["../../1"]}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": ["1/step-3"]}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": ["1\\2"]}),
+        json!({"projectId": "1", "promotionId": "2", "step": "3", "archivedPromotionIds": ["1.5"]}),
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/plagiarism/checks")
+            .set_json(&body)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, request).await.status(),
+            400,
+            "{body}"
+        );
+    }
+
+    let request = test::TestRequest::get()
+        .uri("/plagiarism/checks/unknown")
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+}